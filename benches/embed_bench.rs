@@ -6,8 +6,8 @@ mod v2 {
     use super::BONAFIDE_MODULE_SRC;
     use criterion::{criterion_group, Criterion};
     use ruwren::{
-        get_slot_checked, send_foreign, wren_impl, wren_module, FunctionSignature, ModuleLibrary,
-        VMConfig, WrenObject, VM,
+        foreign_v2::WrenValue, get_slot_checked, send_foreign, wren_impl, wren_module,
+        FunctionSignature, ModuleLibrary, VMConfig, WrenObject, VM,
     };
 
     #[derive(WrenObject, Default)]
@@ -62,6 +62,44 @@ mod v2 {
         });
     }
 
+    fn call_handle_benchmark(c: &mut Criterion) {
+        let vm = VMConfig::new().build();
+
+        vm.interpret(
+            "main",
+            r#"
+    class GameEngine {
+        static update(delta) { delta }
+    }
+    "#,
+        )
+        .unwrap();
+
+        c.bench_function("call by signature", |b| {
+            b.iter(|| {
+                vm.execute(|vm| {
+                    vm.ensure_slots(2);
+                    vm.get_variable("main", "GameEngine", 0);
+                    vm.set_slot_double(1, 0.016);
+                });
+                vm.call(FunctionSignature::new_function("update", 1))
+                    .unwrap();
+            })
+        });
+
+        let handle = vm.make_call_handle(FunctionSignature::new_function("update", 1));
+        c.bench_function("call by cached handle", |b| {
+            b.iter(|| {
+                vm.execute(|vm| {
+                    vm.ensure_slots(1);
+                    vm.get_variable("main", "GameEngine", 0);
+                });
+                vm.call_function_by_handle(0, &handle, &[WrenValue::Number(0.016)])
+                    .unwrap();
+            })
+        });
+    }
+
     trait Boxx {
         fn flipp(&self) -> u32;
     }
@@ -161,7 +199,12 @@ mod v2 {
         });
     }
 
-    criterion_group!(benches_v2, fibonacci_benchmark, foreign_box_benchmark);
+    criterion_group!(
+        benches_v2,
+        fibonacci_benchmark,
+        foreign_box_benchmark,
+        call_handle_benchmark
+    );
 }
 
 mod v1 {
@@ -1,6 +1,7 @@
 use ruwren::{
     foreign_v2::{
-        get_slot_object, get_slot_value, ForeignItem, InputSlot, Slottable, V2Class, WrenTo,
+        get_slot_object, get_slot_value, ForeignItem, InputSlot, Slottable, V2Class,
+        WrenForeignType, WrenTo,
     },
     ClassObject, ModuleLibrary, VMConfig, VM,
 };
@@ -334,11 +335,15 @@ impl V2Class for FooClass {
         "Foo"
     }
 
-    fn allocate() -> Self {
+    fn allocate(_vm: &VM) -> Self {
         FooClass::new()
     }
 }
 
+impl WrenForeignType for Foo {}
+impl WrenForeignType for FooClass {}
+impl WrenForeignType for FooInstance {}
+
 impl ForeignItem for FooInstance {
     type Class = FooClass;
     type Source = Foo;
@@ -417,13 +422,7 @@ impl ClassObject for FooInstance {
                 drop(take_hook());
                 // Copy the object pointer if we were successful
                 if let Some(object) = object {
-                    std::ptr::write(
-                        wptr as *mut _,
-                        ruwren::ForeignObject {
-                            object: Box::into_raw(Box::new(object)),
-                            type_id: std::any::TypeId::of::<FooInstance>(),
-                        },
-                    );
+                    std::ptr::write(wptr as *mut _, ruwren::ForeignObject::new(&vm_borrow, object));
                 }
                 std::ptr::write_unaligned(
                     ruwren::wren_sys::wrenGetUserData(ovm) as *mut ruwren::UserData,
@@ -443,10 +442,7 @@ impl ClassObject for FooInstance {
             unsafe {
                 let mut fo: ruwren::ForeignObject<FooInstance> =
                     std::ptr::read_unaligned(data as *mut _);
-                if !fo.object.is_null() {
-                    _ = Box::from_raw(fo.object);
-                }
-                fo.object = std::ptr::null_mut();
+                fo.finalize();
                 std::ptr::write_unaligned(data as *mut _, fo);
             }
         }
@@ -459,7 +455,7 @@ impl ClassObject for FooInstance {
         Self: Sized,
     {
         ruwren::ClassObjectPointers {
-            function_pointers: vec![
+            function_pointers: Box::new([
                 ruwren::MethodPointer {
                     is_static: true,
                     signature: ruwren::FunctionSignature::new_function("static_fn", 2),
@@ -480,7 +476,7 @@ impl ClassObject for FooInstance {
                     signature: ruwren::FunctionSignature::new_setter("bar"),
                     pointer: FooWrapper::native_vm_bar,
                 },
-            ],
+            ]),
         }
     }
 }
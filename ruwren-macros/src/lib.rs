@@ -1,12 +1,18 @@
-use std::io::Write;
-
 use proc_macro2::Span;
 use quote::{quote, quote_spanned, ToTokens};
 use syn::{
     braced, parse::Parse, parse_macro_input, parse_quote, punctuated::Punctuated, spanned::Spanned,
-    Data, DeriveInput, ImplItem, ImplItemFn, ReturnType, Token, Type, Visibility,
+    Data, DeriveInput, ImplItem, ImplItemFn, ReturnType, Token, Type,
 };
 
+/// Print a macro's expansion when building with `RUWREN_MACRO_DEBUG=1`
+fn trace_expansion(macro_name: &str, expanded: &proc_macro2::TokenStream) {
+    if std::env::var("RUWREN_MACRO_DEBUG").is_ok_and(|v| v == "1") {
+        eprintln!("--- {} -----------------------------", macro_name);
+        eprintln!("{}", expanded);
+    }
+}
+
 fn generate_wrapper_type_name(name: &syn::Ident) -> syn::Ident {
     syn::Ident::new(&format!("{name}Wrapper"), Span::call_site())
 }
@@ -19,6 +25,28 @@ fn generate_instance_type_name(name: &syn::Ident) -> syn::Ident {
     syn::Ident::new(&format!("{name}Instance"), Span::call_site())
 }
 
+/// `register_foo_bar` for a `FooBar` listed in a `wren_module!`
+fn generate_register_fn_name(tp: &syn::TypePath) -> syn::Ident {
+    let name = tp
+        .path
+        .segments
+        .last()
+        .map_or_else(String::new, |seg| seg.ident.to_string());
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_lower) {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
+    }
+    syn::Ident::new(&format!("register_{}", snake), tp.span())
+}
+
 fn generate_class_type(tp: &syn::TypePath) -> syn::TypePath {
     let qself = tp.qself.clone();
     let mut path = tp.path.clone();
@@ -41,10 +69,53 @@ fn generate_instance_type(tp: &syn::TypePath) -> syn::TypePath {
     syn::TypePath { qself, path }
 }
 
+/// The generics of `FooWrapper`, which borrows both halves for `'a`
+fn generate_wrapper_generics(generics: &syn::Generics) -> syn::Generics {
+    let mut generics = generics.clone();
+    generics.params.insert(0, parse_quote! { 'a });
+    generics
+}
+
+struct UnusedTypeParams<'g> {
+    unused: Vec<&'g syn::Ident>,
+}
+
+impl<'a, 'g> syn::visit::Visit<'a> for UnusedTypeParams<'g> {
+    fn visit_type_path(&mut self, tp: &'a syn::TypePath) {
+        if tp.qself.is_none() && tp.path.leading_colon.is_none() {
+            if let Some(first) = tp.path.segments.first() {
+                self.unused.retain(|param| **param != first.ident);
+            }
+        }
+        syn::visit::visit_type_path(self, tp);
+    }
+}
+
+/// A `PhantomData` over the type parameters that none of `fields` mention,
+/// so the half of the split that gets those fields still uses every parameter
+fn generate_phantom(
+    generics: &syn::Generics, fields: &[&syn::Field],
+) -> Option<proc_macro2::TokenStream> {
+    let mut finder = UnusedTypeParams {
+        unused: generics.type_params().map(|tp| &tp.ident).collect(),
+    };
+    for f in fields {
+        syn::visit::Visit::visit_type(&mut finder, &f.ty);
+    }
+    let unused = finder.unused;
+    (!unused.is_empty()).then(|| {
+        quote! {
+            std::marker::PhantomData<fn() -> (#(#unused,)*)>
+        }
+    })
+}
+
 fn generate_class(
-    name: &syn::Ident, fields: &syn::Fields, field_data: &[(&syn::Field, WrenObjectFieldDecl)],
+    name: &syn::Ident, generics: &syn::Generics, fields: &syn::Fields,
+    field_data: &[(&syn::Field, WrenObjectFieldDecl)],
 ) -> proc_macro2::TokenStream {
     let cname = generate_class_type_name(name);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
     match fields {
         syn::Fields::Unit => {
             quote! {
@@ -61,9 +132,16 @@ fn generate_class(
         syn::Fields::Named(_) => {
             let valid: Vec<_> = field_data
                 .iter()
-                .filter_map(|(f, decl)| if decl.static_member { Some(*f) } else { None })
+                .filter_map(|(f, decl)| {
+                    if decl.static_member && !decl.skip {
+                        Some(*f)
+                    } else {
+                        None
+                    }
+                })
                 .collect();
-            let extract: Vec<_> = valid
+            let phantom = generate_phantom(generics, &valid);
+            let mut extract: Vec<_> = valid
                 .iter()
                 .map(|f| {
                     let name = f.ident.as_ref().unwrap();
@@ -72,7 +150,7 @@ fn generate_class(
                     }
                 })
                 .collect();
-            let decls: Vec<_> = valid
+            let mut decls: Vec<_> = valid
                 .into_iter()
                 .map(|f| {
                     // We can unwrap, because fields are definitely named
@@ -83,16 +161,20 @@ fn generate_class(
                     }
                 })
                 .collect();
+            if let Some(phantom) = phantom {
+                extract.push(quote! { __wren_phantom: std::marker::PhantomData });
+                decls.push(quote! { __wren_phantom: #phantom });
+            }
             quote! {
-                struct #cname {
+                struct #cname #generics #where_clause {
                     #(
                         #decls
                     ),*
                 }
 
-                impl From<#name> for #cname {
+                impl #impl_generics From<#name #ty_generics> for #cname #ty_generics #where_clause {
                     #[inline]
-                    fn from(source: #name) -> Self {
+                    fn from(source: #name #ty_generics) -> Self {
                         Self {
                             #(
                                 #extract
@@ -107,15 +189,19 @@ fn generate_class(
                 .iter()
                 .enumerate()
                 .filter_map(|(i, (f, decl))| {
-                    if decl.static_member {
+                    if decl.static_member && !decl.skip {
                         Some((i, f))
                     } else {
                         None
                     }
                 })
                 .collect();
-            if !valid.is_empty() {
-                let extract: Vec<_> = valid
+            let phantom = generate_phantom(
+                generics,
+                &valid.iter().map(|(_, f)| **f).collect::<Vec<_>>(),
+            );
+            if !valid.is_empty() || phantom.is_some() {
+                let mut extract: Vec<_> = valid
                     .iter()
                     .map(|(src_idx, f)| {
                         let idx = syn::Index::from(*src_idx);
@@ -124,7 +210,7 @@ fn generate_class(
                         }
                     })
                     .collect();
-                let decls: Vec<_> = valid
+                let mut decls: Vec<_> = valid
                     .into_iter()
                     .map(|(_, f)| {
                         let ty = &f.ty;
@@ -133,16 +219,20 @@ fn generate_class(
                         }
                     })
                     .collect();
+                if let Some(phantom) = phantom {
+                    extract.push(quote! { std::marker::PhantomData });
+                    decls.push(phantom);
+                }
                 quote! {
-                    struct #cname (
+                    struct #cname #generics (
                         #(
                             #decls
                         ),*
-                    );
+                    ) #where_clause;
 
-                    impl From<#name> for #cname {
+                    impl #impl_generics From<#name #ty_generics> for #cname #ty_generics #where_clause {
                         #[inline]
-                        fn from(source: #name) -> Self {
+                        fn from(source: #name #ty_generics) -> Self {
                             Self (
                                 #(
                                     #extract
@@ -168,9 +258,11 @@ fn generate_class(
 }
 
 fn generate_instance(
-    name: &syn::Ident, fields: &syn::Fields, field_data: &[(&syn::Field, WrenObjectFieldDecl)],
+    name: &syn::Ident, generics: &syn::Generics, fields: &syn::Fields,
+    field_data: &[(&syn::Field, WrenObjectFieldDecl)],
 ) -> proc_macro2::TokenStream {
     let iname = generate_instance_type_name(name);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
     match fields {
         syn::Fields::Unit => {
             quote! {
@@ -187,9 +279,16 @@ fn generate_instance(
         syn::Fields::Named(_) => {
             let valid: Vec<_> = field_data
                 .iter()
-                .filter_map(|(f, decl)| if !decl.static_member { Some(*f) } else { None })
+                .filter_map(|(f, decl)| {
+                    if !decl.static_member && !decl.skip {
+                        Some(*f)
+                    } else {
+                        None
+                    }
+                })
                 .collect();
-            let extract: Vec<_> = valid
+            let phantom = generate_phantom(generics, &valid);
+            let mut extract: Vec<_> = valid
                 .iter()
                 .map(|f| {
                     let name = f.ident.as_ref().unwrap();
@@ -198,7 +297,7 @@ fn generate_instance(
                     }
                 })
                 .collect();
-            let decls: Vec<_> = valid
+            let mut decls: Vec<_> = valid
                 .iter()
                 .map(|f| {
                     // We can unwrap, because fields are definitely named
@@ -210,16 +309,20 @@ fn generate_instance(
                     }
                 })
                 .collect();
+            if let Some(phantom) = phantom {
+                extract.push(quote! { __wren_phantom: std::marker::PhantomData });
+                decls.push(quote! { __wren_phantom: #phantom });
+            }
             quote! {
-                struct #iname {
+                struct #iname #generics #where_clause {
                     #(
                         #decls
                     ),*
                 }
 
-                impl From<#name> for #iname {
+                impl #impl_generics From<#name #ty_generics> for #iname #ty_generics #where_clause {
                     #[inline]
-                    fn from(source: #name) -> Self {
+                    fn from(source: #name #ty_generics) -> Self {
                         Self {
                             #(
                                 #extract
@@ -234,15 +337,19 @@ fn generate_instance(
                 .iter()
                 .enumerate()
                 .filter_map(|(i, (f, decl))| {
-                    if !decl.static_member {
+                    if !decl.static_member && !decl.skip {
                         Some((i, f))
                     } else {
                         None
                     }
                 })
                 .collect();
-            if !valid.is_empty() {
-                let extract: Vec<_> = valid
+            let phantom = generate_phantom(
+                generics,
+                &valid.iter().map(|(_, f)| **f).collect::<Vec<_>>(),
+            );
+            if !valid.is_empty() || phantom.is_some() {
+                let mut extract: Vec<_> = valid
                     .iter()
                     .map(|(src_idx, f)| {
                         let idx = syn::Index::from(*src_idx);
@@ -251,25 +358,30 @@ fn generate_instance(
                         }
                     })
                     .collect();
-                let decls: Vec<_> = valid
+                let mut decls: Vec<_> = valid
                     .into_iter()
                     .map(|(_, f)| {
                         let ty = &f.ty;
+                        let vis = &f.vis;
                         quote_spanned! {f.span()=>
-                            #ty
+                            #vis #ty
                         }
                     })
                     .collect();
+                if let Some(phantom) = phantom {
+                    extract.push(quote! { std::marker::PhantomData });
+                    decls.push(phantom);
+                }
                 quote! {
-                    struct #iname (
+                    struct #iname #generics (
                         #(
                             #decls
                         ),*
-                    );
+                    ) #where_clause;
 
-                    impl From<#name> for #iname {
+                    impl #impl_generics From<#name #ty_generics> for #iname #ty_generics #where_clause {
                         #[inline]
-                        fn from(source: #name) -> Self {
+                        fn from(source: #name #ty_generics) -> Self {
                             Self (
                                 #(
                                     #extract
@@ -294,53 +406,136 @@ fn generate_instance(
     }
 }
 
-fn generate_wrapper(name: &syn::Ident) -> proc_macro2::TokenStream {
+fn generate_wrapper(name: &syn::Ident, generics: &syn::Generics) -> proc_macro2::TokenStream {
     let wname = generate_wrapper_type_name(name);
     let iname = generate_instance_type_name(name);
     let cname = generate_class_type_name(name);
+    let (_, ty_generics, where_clause) = generics.split_for_impl();
+    let wrapper_generics = generate_wrapper_generics(generics);
+    let (wrapper_impl_generics, wrapper_ty_generics, _) = wrapper_generics.split_for_impl();
 
     quote! {
-        struct #wname<'a> {
-            class: &'a mut #cname,
-            instance: &'a mut #iname,
+        struct #wname #wrapper_generics #where_clause {
+            class: &'a mut #cname #ty_generics,
+            instance: &'a mut #iname #ty_generics,
         }
 
-        impl<'a> From<&#wname<'a>> for #name {
+        impl #wrapper_impl_generics From<&#wname #wrapper_ty_generics> for #name #ty_generics #where_clause {
             #[inline]
-            fn from(wrapper: &#wname<'a>) -> Self {
+            fn from(wrapper: &#wname #wrapper_ty_generics) -> Self {
                 (&*wrapper.class, &*wrapper.instance).into()
             }
         }
 
-        impl<'a> From<(&'a mut #cname, &'a mut #iname)> for #wname<'a> {
+        impl #wrapper_impl_generics From<(&'a mut #cname #ty_generics, &'a mut #iname #ty_generics)> for #wname #wrapper_ty_generics #where_clause {
             #[inline]
-            fn from((class, instance): (&'a mut #cname, &'a mut #iname)) -> Self {
+            fn from((class, instance): (&'a mut #cname #ty_generics, &'a mut #iname #ty_generics)) -> Self {
                 Self { class, instance }
             }
         }
 
-        impl<'a> std::ops::Deref for #wname<'a> {
-            type Target = #iname;
+        impl #wrapper_impl_generics #wname #wrapper_ty_generics #where_clause {
+            #[allow(dead_code)]
+            #[inline]
+            fn new(class: &'a mut #cname #ty_generics, instance: &'a mut #iname #ty_generics) -> Self {
+                (class, instance).into()
+            }
+        }
+
+        impl #wrapper_impl_generics std::ops::Deref for #wname #wrapper_ty_generics #where_clause {
+            type Target = #iname #ty_generics;
             #[inline]
-            fn deref(&self) -> &#iname {
+            fn deref(&self) -> &#iname #ty_generics {
                 &self.instance
             }
         }
 
-        impl<'a> std::ops::DerefMut for #wname<'a> {
+        impl #wrapper_impl_generics std::ops::DerefMut for #wname #wrapper_ty_generics #where_clause {
             #[inline]
-            fn deref_mut(&mut self) -> &mut #iname {
+            fn deref_mut(&mut self) -> &mut #iname #ty_generics {
                 &mut self.instance
             }
         }
+
+        impl #wrapper_impl_generics std::borrow::Borrow<#iname #ty_generics> for #wname #wrapper_ty_generics #where_clause {
+            #[inline]
+            fn borrow(&self) -> &#iname #ty_generics {
+                self.instance
+            }
+        }
+
+        impl #wrapper_impl_generics std::borrow::BorrowMut<#iname #ty_generics> for #wname #wrapper_ty_generics #where_clause {
+            #[inline]
+            fn borrow_mut(&mut self) -> &mut #iname #ty_generics {
+                self.instance
+            }
+        }
     }
 }
 
+/// Generate the debug-only checks for each `#[wren(validator = "...")]` field,
+/// run when the source type is rebuilt from the class and instance
+fn generate_validators(
+    name: &syn::Ident, field_data: &[(&syn::Field, WrenObjectFieldDecl)], errors: &deluxe::Errors,
+) -> Vec<proc_macro2::TokenStream> {
+    field_data
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, dat))| !dat.skip)
+        .scan((0, 0), |(ci, ii), (idx, (f, dat))| {
+            // Tuple fields are renumbered on each side of the split
+            let side_idx = if dat.static_member { ci } else { ii };
+            let member = match &f.ident {
+                Some(ident) => quote! { #ident },
+                None => {
+                    let side = syn::Index::from(*side_idx);
+                    quote! { #side }
+                }
+            };
+            *side_idx += 1;
+            let owner = if dat.static_member {
+                quote! { class }
+            } else {
+                quote! { inst }
+            };
+            let label = match &f.ident {
+                Some(ident) => format!("{}.{}", name, ident),
+                None => format!("{}.{}", name, idx),
+            };
+            Some((f, dat, quote! { #owner.#member }, label))
+        })
+        .filter_map(|(f, dat, value, label)| {
+            let validator = dat.validator.as_ref()?;
+            let Ok(validator) = syn::parse_str::<syn::Path>(validator) else {
+                errors.push(
+                    f.span(),
+                    format!(
+                        "validator must be a function path, but got \"{}\"",
+                        validator
+                    ),
+                );
+                return None;
+            };
+            Some(quote_spanned! {f.span()=>
+                #[cfg(debug_assertions)]
+                if let Err(err) = #validator(&#value) {
+                    panic!("invalid value for {}: {}", #label, err);
+                }
+            })
+        })
+        .collect()
+}
+
 fn generate_enhancements(
-    name: &syn::Ident, fields: &syn::Fields, field_data: &[(&syn::Field, WrenObjectFieldDecl)],
+    name: &syn::Ident, generics: &syn::Generics, fields: &syn::Fields,
+    field_data: &[(&syn::Field, WrenObjectFieldDecl)], errors: &deluxe::Errors,
 ) -> proc_macro2::TokenStream {
     let class_name = generate_class_type_name(name);
     let instance_name = generate_instance_type_name(name);
+    let wrapper_generics = generate_wrapper_generics(generics);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let (wrapper_impl_generics, _, _) = wrapper_generics.split_for_impl();
+    let validators = generate_validators(name, field_data, errors);
 
     let from_impl = match fields {
         syn::Fields::Unit => {
@@ -354,7 +549,11 @@ fn generate_enhancements(
                 .map(|(f, dat)| {
                     // We can unwrap, because fields are definitely named
                     let name = f.ident.as_ref().unwrap();
-                    if dat.static_member {
+                    if dat.skip {
+                        quote_spanned! {f.span()=>
+                            #name: Default::default()
+                        }
+                    } else if dat.static_member {
                         quote_spanned! {f.span()=>
                             #name: class.#name.clone()
                         }
@@ -379,7 +578,11 @@ fn generate_enhancements(
                     .iter()
                     .scan((0, 0), |(ci, ii), (f, dat)| {
                         // We can unwrap, because fields are definitely named
-                        if dat.static_member {
+                        if dat.skip {
+                            Some(quote_spanned! {f.span()=>
+                                Default::default()
+                            })
+                        } else if dat.static_member {
                             let idx = syn::Index::from(*ci);
                             *ci += 1;
                             Some(quote! {
@@ -410,100 +613,708 @@ fn generate_enhancements(
     };
 
     quote! {
-        impl<'a> From<(&'a #class_name, &'a #instance_name)> for #name {
+        impl #wrapper_impl_generics From<(&'a #class_name #ty_generics, &'a #instance_name #ty_generics)> for #name #ty_generics #where_clause {
             #[allow(clippy::clone_on_copy)]
             #[inline]
-            fn from((class, inst): (&'a #class_name, &'a #instance_name)) -> Self {
+            fn from((class, inst): (&'a #class_name #ty_generics, &'a #instance_name #ty_generics)) -> Self {
+                #(
+                    #validators
+                )*
                 #from_impl
             }
         }
 
-        impl TryFrom<Option<#name>> for #name {
+        impl #impl_generics TryFrom<Option<#name #ty_generics>> for #name #ty_generics #where_clause {
             type Error = ();
 
-            fn try_from(value: Option<#name>) -> Result<Self, Self::Error> {
+            fn try_from(value: Option<#name #ty_generics>) -> Result<Self, Self::Error> {
                 value.ok_or(())
             }
         }
     }
 }
 
-#[derive(deluxe::ExtractAttributes)]
+/// Generate `get_x`/`set_x` methods on the wrapper for every `#[wren(accessor)]` field,
+/// reading from the class or the instance depending on where the field ended up
+fn generate_accessors(
+    name: &syn::Ident, generics: &syn::Generics, field_data: &[(&syn::Field, WrenObjectFieldDecl)],
+) -> proc_macro2::TokenStream {
+    let wname = generate_wrapper_type_name(name);
+    let wrapper_generics = generate_wrapper_generics(generics);
+    let (impl_generics, ty_generics, where_clause) = wrapper_generics.split_for_impl();
+    let accessors: Vec<_> = field_data
+        .iter()
+        .filter(|(_, dat)| dat.accessor)
+        .map(|(f, dat)| {
+            let Some(field) = f.ident.as_ref() else {
+                return quote_spanned! {f.span()=>
+                    compile_error!("accessor is only supported on named fields");
+                };
+            };
+            let ty = &f.ty;
+            let getter = syn::Ident::new(&format!("get_{}", field), field.span());
+            let setter = syn::Ident::new(&format!("set_{}", field), field.span());
+            let owner = if dat.static_member {
+                quote! { class }
+            } else {
+                quote! { instance }
+            };
+            quote_spanned! {f.span()=>
+                #[allow(clippy::clone_on_copy)]
+                #[inline]
+                pub fn #getter(&self) -> #ty {
+                    self.#owner.#field.clone()
+                }
+
+                #[inline]
+                pub fn #setter(&mut self, value: #ty) {
+                    self.#owner.#field = value;
+                }
+            }
+        })
+        .collect();
+
+    if accessors.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            #[allow(dead_code)]
+            impl #impl_generics #wname #ty_generics #where_clause {
+                #(
+                    #accessors
+                )*
+            }
+        }
+    }
+}
+
+#[derive(Default, deluxe::ExtractAttributes)]
+#[deluxe(default, attributes(wren))]
+struct WrenObjectDecl {
+    // The name of the class on the Wren side, if it isn't the struct's name
+    rename: Option<String>,
+    // Named instantiations of a generic struct, which wren_impl can then be used on
+    #[deluxe(append)]
+    concrete: Vec<ConcreteDecl>,
+}
+
+#[derive(deluxe::ParseMetaItem)]
+struct ConcreteDecl {
+    name: syn::Ident,
+    #[deluxe(rest)]
+    params: std::collections::HashMap<syn::Path, ConcreteArg>,
+}
+
+/// A type or const argument of a concrete declaration, like the `f32` in `T = f32` or the `4` in
+/// `N = 4`
+struct ConcreteArg(syn::GenericArgument);
+
+impl deluxe::ParseMetaItem for ConcreteArg {
+    fn parse_meta_item(
+        input: syn::parse::ParseStream, _mode: deluxe::ParseMode,
+    ) -> deluxe::Result<Self> {
+        input.parse().map(Self)
+    }
+}
+
+/// The identifiers of the type and const parameters, in the order they're declared
+fn generic_arg_idents(generics: &syn::Generics) -> impl Iterator<Item = &syn::Ident> {
+    generics.params.iter().filter_map(|param| match param {
+        syn::GenericParam::Type(tp) => Some(&tp.ident),
+        syn::GenericParam::Const(cp) => Some(&cp.ident),
+        syn::GenericParam::Lifetime(_) => None,
+    })
+}
+
+/// Generate the `type` aliases for each `#[wren(concrete(name = FooF32, T = f32))]`: `FooF32`,
+/// `FooF32Class`, `FooF32Instance` and `FooF32Wrapper`, which line up with what `wren_impl` expects
+fn generate_concrete_aliases(
+    source_ty: &syn::Ident, generics: &syn::Generics, vis: &syn::Visibility,
+    decls: &[ConcreteDecl], errors: &deluxe::Errors,
+) -> proc_macro2::TokenStream {
+    let class_ty = generate_class_type_name(source_ty);
+    let instance_ty = generate_instance_type_name(source_ty);
+    let wrapper_ty = generate_wrapper_type_name(source_ty);
+    let hidden = matches!(vis, syn::Visibility::Inherited).then(|| quote! { #[doc(hidden)] });
+
+    let aliases = decls.iter().filter_map(|decl| {
+        for key in decl.params.keys() {
+            if !generic_arg_idents(generics).any(|ident| key.is_ident(ident)) {
+                errors.push_spanned(
+                    key,
+                    format!(
+                        "{} isn't a type or const parameter of {}",
+                        key.to_token_stream(),
+                        source_ty
+                    ),
+                );
+            }
+        }
+        let args = generic_arg_idents(generics)
+            .map(|ident| {
+                let arg = decl
+                    .params
+                    .iter()
+                    .find_map(|(key, arg)| key.is_ident(ident).then_some(&arg.0));
+                if arg.is_none() {
+                    errors.push_spanned(
+                        &decl.name,
+                        format!("concrete {} is missing parameter {}", decl.name, ident),
+                    );
+                }
+                arg
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let name = &decl.name;
+        let class_name = generate_class_type_name(name);
+        let instance_name = generate_instance_type_name(name);
+        let wrapper_name = generate_wrapper_type_name(name);
+        Some(quote! {
+            #vis type #name = #source_ty<#(#args),*>;
+            #hidden #vis type #class_name = #class_ty<#(#args),*>;
+            #hidden #vis type #instance_name = #instance_ty<#(#args),*>;
+            #hidden #vis type #wrapper_name<'a> = #wrapper_ty<'a, #(#args),*>;
+        })
+    });
+
+    quote! {
+        #(
+            #aliases
+        )*
+    }
+}
+
+#[derive(Default, deluxe::ExtractAttributes)]
 #[deluxe(attributes(wren))]
 struct WrenObjectFieldDecl {
     #[deluxe(default)]
     static_member: bool,
+    // Leave the field out of both halves, and rebuild it with Default
+    #[deluxe(default)]
+    skip: bool,
+    // Generate get_x/set_x on the wrapper
+    #[deluxe(default)]
+    accessor: bool,
+    // Function to call with this field when the instance is freed. The field is moved out with
+    // mem::take, so it needs to be Default (but not Copy)
+    #[deluxe(default)]
+    drop_on_wren_free: Option<String>,
+    // fn(&T) -> Result<(), String> checked (in debug builds) when the source type is rebuilt
+    #[deluxe(default)]
+    validator: Option<String>,
+}
+
+/// Generate a `Drop` impl for the instance type, calling each field's `drop_on_wren_free` function
+fn generate_instance_drop(
+    name: &syn::Ident, generics: &syn::Generics, field_data: &[(&syn::Field, WrenObjectFieldDecl)],
+    errors: &deluxe::Errors,
+) -> proc_macro2::TokenStream {
+    let iname = generate_instance_type_name(name);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let releases: Vec<_> = field_data
+        .iter()
+        .filter(|(_, dat)| !dat.static_member && !dat.skip)
+        .enumerate()
+        .filter_map(|(idx, (f, dat))| {
+            let release = dat.drop_on_wren_free.as_ref()?;
+            let Ok(release) = syn::parse_str::<syn::Path>(release) else {
+                errors.push(
+                    f.span(),
+                    format!(
+                        "drop_on_wren_free must be a function path, but got \"{}\"",
+                        release
+                    ),
+                );
+                return None;
+            };
+            let field = match &f.ident {
+                Some(ident) => quote! { #ident },
+                None => {
+                    let idx = syn::Index::from(idx);
+                    quote! { #idx }
+                }
+            };
+            Some(quote_spanned! {f.span()=>
+                #release(std::mem::take(&mut self.#field));
+            })
+        })
+        .collect();
+
+    for (f, _) in field_data
+        .iter()
+        .filter(|(_, dat)| dat.static_member && dat.drop_on_wren_free.is_some())
+    {
+        errors.push(
+            f.span(),
+            "drop_on_wren_free can only be used on instance fields, not a static_member",
+        );
+    }
+
+    if releases.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            impl #impl_generics Drop for #iname #ty_generics #where_clause {
+                fn drop(&mut self) {
+                    #(
+                        #releases
+                    )*
+                }
+            }
+        }
+    }
+}
+
+/// The enum version of [`wren_object_derive`]: `FooClass` is empty, and `FooInstance` holds the
+/// whole value (so variants can carry data). Each unit variant also gets a static getter on the
+/// Wren class, named after the variant. Unit variants are numbered consecutively from 0 (data
+/// variants are skipped), and an explicit discriminant restarts the numbering from its value.
+fn generate_enum_object(
+    input: &DeriveInput, data: &syn::DataEnum, wren_name: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let source_ty = &input.ident;
+    let class_ty = generate_class_type_name(source_ty);
+    let instance_ty = generate_instance_type_name(source_ty);
+    let wrapper_type = generate_wrapper(source_ty, &input.generics);
+    let vis = &input.vis;
+    let hidden = matches!(vis, syn::Visibility::Inherited).then(|| quote! { #[doc(hidden)] });
+
+    // The last explicit discriminant (if any), and how many unit variants came since,
+    // for the previous unit variant
+    let mut numbering: Option<(Option<&syn::Expr>, usize)> = None;
+    let (natives, pointers): (Vec<_>, Vec<_>) = data
+        .variants
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| matches!(v.fields, syn::Fields::Unit))
+        .map(|(idx, v)| {
+            let current = match (&v.discriminant, numbering) {
+                (Some((_, expr)), _) => (Some(expr), 0),
+                (None, Some((last, offset))) => (last, offset + 1),
+                (None, None) => (None, 0),
+            };
+            numbering = Some(current);
+            let native = syn::Ident::new(&format!("native_variant_{}", idx), v.span());
+            let name = v.ident.to_string();
+            let value = match current {
+                (Some(expr), offset) => quote! { (#expr) as f64 + #offset as f64 },
+                (None, offset) => quote! { #offset as f64 },
+            };
+            (
+                quote_spanned! {v.span()=>
+                    #[doc(hidden)]
+                    pub unsafe extern "C" fn #native(vm: *mut ruwren::wren_sys::WrenVM) {
+                        ruwren::wren_sys::wrenSetSlotDouble(vm, 0, #value);
+                    }
+                },
+                quote! {
+                    ruwren::MethodPointer {
+                        is_static: true,
+                        signature: ruwren::FunctionSignature::new_getter(#name),
+                        pointer: #class_ty::#native,
+                    }
+                },
+            )
+        })
+        .unzip();
+
+    quote! {
+        impl ruwren::foreign_v2::WrenForeignType for #source_ty {}
+        impl ruwren::foreign_v2::WrenForeignType for #class_ty {}
+        impl ruwren::foreign_v2::WrenForeignType for #instance_ty {}
+
+        #hidden #vis struct #class_ty;
+
+        impl From<#source_ty> for #class_ty {
+            #[inline]
+            fn from(_: #source_ty) -> Self {
+                Self
+            }
+        }
+
+        impl #class_ty {
+            #(
+                #natives
+            )*
+        }
+
+        impl ruwren::foreign_v2::DerivedItems for #source_ty {
+            #wren_name
+
+            fn derived_methods() -> Vec<ruwren::MethodPointer> {
+                vec![
+                    #(
+                        #pointers
+                    ),*
+                ]
+            }
+        }
+
+        #hidden #vis struct #instance_ty(#source_ty);
+
+        impl From<#source_ty> for #instance_ty {
+            #[inline]
+            fn from(source: #source_ty) -> Self {
+                Self(source)
+            }
+        }
+
+        impl std::ops::Deref for #instance_ty {
+            type Target = #source_ty;
+            #[inline]
+            fn deref(&self) -> &#source_ty {
+                &self.0
+            }
+        }
+
+        impl std::ops::DerefMut for #instance_ty {
+            #[inline]
+            fn deref_mut(&mut self) -> &mut #source_ty {
+                &mut self.0
+            }
+        }
+
+        impl<'a> From<(&'a #class_ty, &'a #instance_ty)> for #source_ty {
+            #[inline]
+            fn from((_, inst): (&'a #class_ty, &'a #instance_ty)) -> Self {
+                inst.0.clone()
+            }
+        }
+
+        impl TryFrom<Option<#source_ty>> for #source_ty {
+            type Error = ();
+
+            fn try_from(value: Option<#source_ty>) -> Result<Self, Self::Error> {
+                value.ok_or(())
+            }
+        }
+
+        #hidden #vis #wrapper_type
+    }
 }
 
+/// Splits a struct into `FooClass` (fields marked `#[wren(static_member)]`),
+/// `FooInstance` (every other field), and a `FooWrapper` over both.
+///
+/// Fields are instance data unless annotated, and this holds for tuple structs too:
+/// `struct Config(f64, #[wren(static_member)] u32)` gives a `ConfigInstance(f64)` and a
+/// `ConfigClass(u32)`. Each side keeps its own fields in order, so indices restart from 0.
+/// Fields marked `#[wren(skip)]` go to neither side, and are filled with `Default::default()`
+/// when the source type is rebuilt.
+///
+/// A field marked `#[wren(validator = "check")]` is passed to `check(&value)` whenever the source
+/// type is rebuilt from the two halves, and an `Err` panics. This only runs in debug builds, and
+/// only methods generated with `#[wren_impl(version = "2")]` turn that panic into a fiber abort.
+///
+/// Structs can have type and const parameters (but not lifetimes), and every generated type
+/// carries them. A foreign class has to be a single concrete type though, so each instantiation
+/// that Wren should see is named with `#[wren(concrete(name = BufferF32, T = f32))]` (a const
+/// parameter is given its value the same way, as in `N = 4`). That generates `BufferF32`,
+/// `BufferF32Class`, `BufferF32Instance` and `BufferF32Wrapper` aliases, and `wren_impl` and
+/// `wren_module` are then used with `BufferF32`, which is also the class's name in Wren.
+/// If a half of the split doesn't use a type parameter, it gets a `__wren_phantom` field for it.
 #[proc_macro_derive(WrenObject, attributes(wren))]
 pub fn wren_object_derive(stream: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let input = parse_macro_input!(stream as DeriveInput);
+    let mut input = parse_macro_input!(stream as DeriveInput);
+
+    // Foreign classes are looked up by TypeId, so borrowed data can't be handed to Wren
+    if let Some(param) = input
+        .generics
+        .params
+        .iter()
+        .find(|p| matches!(p, syn::GenericParam::Lifetime(_)))
+    {
+        return syn::Error::new_spanned(
+            param,
+            "WrenObject can't support lifetime parameters: foreign classes are looked up by TypeId, which only exists for 'static types",
+        )
+        .into_compile_error()
+        .into();
+    }
+    if matches!(input.data, Data::Enum(_)) && !input.generics.params.is_empty() {
+        return syn::Error::new_spanned(
+            &input.generics,
+            "WrenObject doesn't support generic enums",
+        )
+        .into_compile_error()
+        .into();
+    }
+
+    let errors = deluxe::Errors::new();
+
+    let object_decl = match deluxe::extract_attributes::<_, WrenObjectDecl>(&mut input) {
+        Ok(decl) => decl,
+        Err(e) => {
+            errors.push_syn(e);
+            WrenObjectDecl::default()
+        }
+    };
+    if let Some(decl) = object_decl.concrete.first() {
+        if input.generics.params.is_empty() {
+            errors.push_spanned(&decl.name, "concrete is only needed on generic types");
+        }
+    }
+    if let (Some(_), Some(decl)) = (&object_decl.rename, object_decl.concrete.get(1)) {
+        errors.push_spanned(
+            &decl.name,
+            "rename would give every concrete instantiation the same Wren name, use the concrete names instead",
+        );
+    }
+    let wren_name = match &object_decl.rename {
+        Some(rename) => quote! {
+            const WREN_NAME: Option<&'static str> = Some(#rename);
+        },
+        None => quote! {},
+    };
 
     let mut struct_impl = match input.data {
         Data::Struct(s) => s,
+        Data::Enum(ref e) => {
+            // Variants are kept whole in the instance, so field attributes have nothing to do
+            for attr in e
+                .variants
+                .iter()
+                .flat_map(|v| v.fields.iter())
+                .flat_map(|f| f.attrs.iter())
+                .filter(|attr| attr.path().is_ident("wren"))
+            {
+                errors.push_spanned(attr, "wren field attributes are only supported on structs");
+            }
+            let enum_object = generate_enum_object(&input, e, &wren_name);
+            let expanded = quote! {
+                #errors
+                #enum_object
+            };
+            trace_expansion("wren_object_derive", &expanded);
+            return expanded.into();
+        }
         _ => {
             return quote! {
-                compile_error!("only structs are supported")
+                compile_error!("only structs and enums are supported")
             }
             .into()
         }
     };
 
-    let errors = deluxe::Errors::new();
-
-    let field_decls: Option<Vec<WrenObjectFieldDecl>> = struct_impl
+    // A field whose attributes can't be read is reported where it is, and otherwise treated as
+    // a plain instance field, so the rest of the expansion doesn't pile on unrelated errors
+    let field_decls: Vec<WrenObjectFieldDecl> = struct_impl
         .fields
         .iter_mut()
         .map(|f| match deluxe::extract_attributes(f) {
-            Ok(fd) => Some(fd),
+            Ok(fd) => fd,
             Err(e) => {
                 errors.push_syn(e);
-                None
+                WrenObjectFieldDecl::default()
             }
         })
         .collect();
+    let field_decls: Vec<_> = struct_impl.fields.iter().zip(field_decls).collect();
+
+    for (f, dat) in &field_decls {
+        if dat.skip
+            && (dat.static_member
+                || dat.accessor
+                || dat.drop_on_wren_free.is_some()
+                || dat.validator.is_some())
+        {
+            errors.push(
+                f.span(),
+                "skip leaves the field out of both halves, so it can't be combined with other wren attributes",
+            );
+        }
+    }
 
-    let field_decls = if let Some(field_decls) = field_decls {
-        struct_impl.fields.iter().zip(field_decls).collect()
-    } else {
-        errors.push_call_site("A field decl extractor failed.");
-        vec![]
-    };
-
-    let class_type = generate_class(&input.ident, &struct_impl.fields, &field_decls);
-    let instance_type = generate_instance(&input.ident, &struct_impl.fields, &field_decls);
-    let enhancements = generate_enhancements(&input.ident, &struct_impl.fields, &field_decls);
-    let wrapper_type = generate_wrapper(&input.ident);
+    let generics = &input.generics;
+    let class_type = generate_class(&input.ident, generics, &struct_impl.fields, &field_decls);
+    let instance_type =
+        generate_instance(&input.ident, generics, &struct_impl.fields, &field_decls);
+    let enhancements = generate_enhancements(
+        &input.ident,
+        generics,
+        &struct_impl.fields,
+        &field_decls,
+        &errors,
+    );
+    let wrapper_type = generate_wrapper(&input.ident, generics);
+    let accessors = generate_accessors(&input.ident, generics, &field_decls);
+    let instance_drop = generate_instance_drop(&input.ident, generics, &field_decls, &errors);
+    let concrete_aliases = generate_concrete_aliases(
+        &input.ident,
+        generics,
+        &input.vis,
+        &object_decl.concrete,
+        &errors,
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let source_ty = &input.ident;
+    let class_ty = generate_class_type_name(source_ty);
+    let instance_ty = generate_instance_type_name(source_ty);
     let vis = &input.vis;
+    // Private sources keep their generated types out of --document-private-items output too
+    let hidden = matches!(vis, syn::Visibility::Inherited).then(|| quote! { #[doc(hidden)] });
 
     let expanded = quote! {
         #errors
         #enhancements
-        #vis #class_type
-        #vis #instance_type
-        #vis #wrapper_type
+        #accessors
+        #instance_drop
+        impl #impl_generics ruwren::foreign_v2::WrenForeignType for #source_ty #ty_generics #where_clause {}
+        impl #impl_generics ruwren::foreign_v2::WrenForeignType for #class_ty #ty_generics #where_clause {}
+        impl #impl_generics ruwren::foreign_v2::WrenForeignType for #instance_ty #ty_generics #where_clause {}
+        impl #impl_generics ruwren::foreign_v2::DerivedItems for #source_ty #ty_generics #where_clause {
+            #wren_name
+        }
+        #hidden #vis #class_type
+        #hidden #vis #instance_type
+        #hidden #vis #wrapper_type
+        #concrete_aliases
     };
 
-    println!("--- wren_object_derive -----------------------------");
-    writeln!(std::io::stdout(), "{}", expanded);
+    trace_expansion("wren_object_derive", &expanded);
     proc_macro::TokenStream::from(expanded)
 }
 
-#[derive(Clone, Default, deluxe::ExtractAttributes)]
-#[deluxe(default, attributes(wren_impl))]
-struct WrenImplFnAttrs {
-    // [0, 1] required (if 0, will attempt to use Default on Foo to generate FooClass)
-    allocator: bool,
+#[derive(Default, deluxe::ExtractAttributes)]
+#[deluxe(default, attributes(wren))]
+struct WrenFromDecl {
+    // Read this type from the slot, then convert it with the struct's From impl
+    from: Option<syn::Type>,
+}
+
+/// Derives `ruwren::foreign_v2::WrenTryFrom` for a newtype,
+/// so `wren_impl` methods can take it as a parameter.
+///
+/// A struct with one field is read as that field's type. Otherwise, `#[wren(from = f64)]`
+/// reads an `f64` and converts it with the struct's `From<f64>` impl.
+/// `String` is read from a Wren string, and isn't extracted if it isn't UTF-8.
+#[proc_macro_derive(WrenFrom, attributes(wren))]
+pub fn wren_from_derive(stream: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut input = parse_macro_input!(stream as DeriveInput);
+
+    let decl = match deluxe::extract_attributes::<_, WrenFromDecl>(&mut input) {
+        Ok(decl) => decl,
+        Err(e) => return e.into_compile_error().into(),
+    };
+
+    let (source_ty, construct) = match (decl.from, &input.data) {
+        (Some(ty), _) => (ty, quote! { From::from }),
+        (None, Data::Struct(data)) if data.fields.len() == 1 => {
+            let field = data.fields.iter().next().unwrap();
+            let construct = match &field.ident {
+                Some(name) => quote! { |value| Self { #name: value } },
+                None => quote! { Self },
+            };
+            (field.ty.clone(), construct)
+        }
+        _ => {
+            return syn::Error::new_spanned(
+                &input.ident,
+                "WrenFrom needs a struct with exactly one field, or #[wren(from = Type)] to convert with From<Type>",
+            )
+            .into_compile_error()
+            .into()
+        }
+    };
+
+    // Wren strings aren't guaranteed to be UTF-8, so they're read as WrenString
+    let is_string =
+        matches!(&source_ty, Type::Path(tp) if tp.qself.is_none() && tp.path.is_ident("String"));
+    let read_ty: Type = if is_string {
+        parse_quote! { ruwren::foreign_v2::WrenString }
+    } else {
+        source_ty.clone()
+    };
+    let read = if is_string {
+        quote! {
+            .and_then(|value| value.into_string().ok())
+        }
+    } else {
+        quote! {}
+    };
+
+    let name = &input.ident;
+    let mut generics = input.generics.clone();
+    generics
+        .make_where_clause()
+        .predicates
+        .push(parse_quote! { #read_ty: ruwren::foreign_v2::WrenTryFrom });
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let expanded = quote! {
+        impl #impl_generics ruwren::foreign_v2::WrenTryFrom for #name #ty_generics #where_clause {
+            const SCRATCH_SPACE: usize = <#read_ty as ruwren::foreign_v2::WrenTryFrom>::SCRATCH_SPACE;
+            const SLOT_TYPE: Option<ruwren::SlotType> =
+                <#read_ty as ruwren::foreign_v2::WrenTryFrom>::SLOT_TYPE;
+
+            fn try_from_vm(vm: &ruwren::VM, slot: ruwren::SlotId, scratch_start: ruwren::SlotId) -> Option<Self> {
+                <#read_ty as ruwren::foreign_v2::WrenTryFrom>::try_from_vm(vm, slot, scratch_start)
+                    #read
+                    .map(#construct)
+            }
+        }
+    };
+
+    trace_expansion("wren_from_derive", &expanded);
+    proc_macro::TokenStream::from(expanded)
+}
+
+#[derive(Clone, Default, deluxe::ExtractAttributes)]
+#[deluxe(default, attributes(wren_impl))]
+struct WrenImplFnAttrs {
+    // [0, 1] required (if 0, will attempt to use Default on Foo to generate FooClass)
+    allocator: bool,
     // [0, 1] required (if 0, will attempt to use Default on Foo to generate FooInstance)
     constructor: bool,
 
     instance: bool,
     getter: bool,
     setter: bool,
+    // Expose the function as [index] or [index]=(value), instead of by its name
+    subscript_getter: bool,
+    subscript_setter: bool,
 
     ignore: bool, // Alex: I added this
 
+    // Emit the function as-is into the FooClass (or FooWrapper, with instance) impl, without exposing it to Wren
+    pass_through: bool,
+
+    // Pass the &VM as the last argument of the function (after the arguments taken from slots)
+    receive_vm: bool,
+
+    // The name of the method on the Wren side, if it isn't the function's name
+    rename: Option<String>,
+
+    // Expose the function as a Wren operator (+, -, ==, [] and so on) instead of by its name
+    operator: Option<String>,
+
+    // Shorthand for instance, getter, rename = "toString"
+    to_string: bool,
+
     object: Vec<syn::Ident>,
+
+    // Object parameters read from an explicit slot, rather than the one their position implies
+    #[deluxe(append)]
+    object_slot: Vec<ObjectSlotDecl>,
+
+    // Only valid on the constructor, a Rust type exposed through wren_module! that implements
+    // From<String>. A failed constructor aborts with an instance of it (or the plain message, if it
+    // can't be created). It can't be a class written in Wren: Wren can't run a constructor from
+    // inside a foreign allocator (wrenCall refuses to run while a foreign method is on the stack)
+    error_type: Option<String>,
+
+    // [0, 1] allowed, a fn(&mut self) run on the instance when Wren frees it (the VM can't be used)
+    finalizer: bool,
+
+    // Not valid on the constructor, a fn(&VM, &str, SlotId, &str) called (instead of aborting the fiber)
+    // when an argument can't be extracted. It's responsible for aborting the fiber itself.
+    on_error: Option<String>,
+}
+
+#[derive(Clone, deluxe::ParseMetaItem)]
+struct ObjectSlotDecl {
+    name: syn::Ident,
+    slot: usize,
 }
 
 struct WrenImplValidFn {
@@ -511,12 +1322,63 @@ struct WrenImplValidFn {
     is_static: bool,
     is_setter: bool,
     is_getter: bool,
+    is_subscript_setter: bool,
+    is_subscript_getter: bool,
     source_name: Option<syn::Ident>,
     normal_params: Vec<(usize, syn::PatType)>,
     object_params: Vec<(usize, syn::PatType)>,
+    // The slot each parameter is read from, by position
+    slots: Vec<usize>,
+    on_error: Option<syn::Path>,
+    receive_vm: bool,
+    rename: Option<String>,
+    operator: Option<String>,
+    // Check each argument's slot type before extracting it (set from the block's attributes)
+    validate_inputs: bool,
+    // A static method taking &self, so the class is only borrowed immutably while it runs.
+    // Object parameters may be looked up through the class, so they need it mutably
+    shared_class: bool,
     func: ImplItemFn,
 }
 
+/// The number of arguments each overloadable Wren operator can take
+fn operator_arities(operator: &str) -> Option<&'static [usize]> {
+    match operator {
+        "!" | "~" => Some(&[0]),
+        "-" => Some(&[0, 1]),
+        "+" | "*" | "/" | "%" | "<" | ">" | "<=" | ">=" | "==" | "!=" | "&" | "|" | "^" | "<<"
+        | ">>" | ".." | "..." | "[]" => Some(&[1]),
+        "[]=" => Some(&[2]),
+        _ => None,
+    }
+}
+
+/// If `ty` names a `Result` (including aliases like `io::Result<T>`), get its `Ok` type,
+/// when it's spelled out
+fn result_ok_type(ty: &syn::Type) -> Option<Option<&syn::Type>> {
+    let Type::Path(tp) = ty else {
+        return None;
+    };
+    let last = tp.path.segments.last()?;
+    if last.ident != "Result" {
+        return None;
+    }
+    Some(match &last.arguments {
+        syn::PathArguments::AngleBracketed(args) => args.args.iter().find_map(|a| match a {
+            syn::GenericArgument::Type(t) => Some(t),
+            _ => None,
+        }),
+        _ => None,
+    })
+}
+
+/// The message a method aborts with when its argument in `slot_idx` can't be extracted as a `ty`
+fn extract_failure_message(ty: &syn::Type, slot_idx: usize) -> proc_macro2::TokenStream {
+    quote! {
+        format!("failed to get value of type {} for slot {}", std::any::type_name::<#ty>(), #slot_idx)
+    }
+}
+
 struct FindInnerType {
     discovered_tp: Option<syn::TypePath>,
 }
@@ -561,6 +1423,100 @@ impl WrenImplValidFn {
         &self.func.sig.ident
     }
 
+    /// The name this function is registered under in Wren
+    fn wren_name(&self) -> String {
+        self.rename
+            .clone()
+            .unwrap_or_else(|| self.source_name().to_string())
+    }
+
+    /// The `FunctionSignature` this function is registered under
+    fn gen_signature(&self) -> proc_macro2::TokenStream {
+        let name = self.wren_name();
+        let arity = self.arity();
+        if let Some(operator) = &self.operator {
+            // Unary operators are called like getters, -x is x.-
+            match (operator.as_str(), arity) {
+                ("[]", _) => quote! { ruwren::FunctionSignature::new_subscript_getter() },
+                ("[]=", _) => quote! { ruwren::FunctionSignature::new_subscript_setter() },
+                (_, 0) => quote! { ruwren::FunctionSignature::new_getter(#operator) },
+                _ => quote! { ruwren::FunctionSignature::new_function(#operator, #arity) },
+            }
+        } else if self.is_getter {
+            quote! { ruwren::FunctionSignature::new_getter(#name) }
+        } else if self.is_setter {
+            quote! { ruwren::FunctionSignature::new_setter(#name) }
+        } else if self.is_subscript_getter {
+            quote! { ruwren::FunctionSignature::new_subscript_getter() }
+        } else if self.is_subscript_setter {
+            quote! { ruwren::FunctionSignature::new_subscript_setter() }
+        } else {
+            quote! { ruwren::FunctionSignature::new_function(#name, #arity) }
+        }
+    }
+
+    /// The names of the parameters Wren passes, in slot order
+    fn wren_param_names(&self) -> Vec<String> {
+        let mut params: Vec<_> = self
+            .normal_params
+            .iter()
+            .chain(self.object_params.iter())
+            .map(|(idx, pt)| {
+                let slot = self.slots[*idx];
+                let name = match pt.pat.as_ref() {
+                    syn::Pat::Ident(pi) => pi.ident.to_string(),
+                    _ => format!("arg{}", slot - 1),
+                };
+                (slot, name)
+            })
+            .collect();
+        params.sort();
+        params.into_iter().map(|(_, name)| name).collect()
+    }
+
+    /// Generate what happens when the argument in `slot_idx` can't be extracted as a `ty`
+    /// (outside of a constructor), aborting with `message` if there's no `on_error` handler
+    fn gen_extract_failure(
+        &self, ty: &syn::Type, slot_idx: usize, message: proc_macro2::TokenStream,
+    ) -> proc_macro2::TokenStream {
+        match &self.on_error {
+            Some(handler) => {
+                let method_name = self.source_name().to_string();
+                quote! {
+                    #handler(vm, #method_name, #slot_idx, std::any::type_name::<#ty>());
+                    return
+                }
+            }
+            None => quote! {
+                ruwren::foreign_v2::WrenTo::to_vm(#message, vm, 0, 1);
+                vm.abort_fiber(0);
+                return
+            },
+        }
+    }
+
+    /// Generate the `validate_inputs` check for the argument in `slot_idx`, with `check`
+    /// giving a `Result<(), String>`
+    fn gen_validate(
+        &self, ty: &syn::Type, slot_idx: usize, check: proc_macro2::TokenStream,
+        constructor_mode: bool,
+    ) -> proc_macro2::TokenStream {
+        if !self.validate_inputs {
+            quote! {}
+        } else if constructor_mode {
+            quote! {
+                #check?;
+            }
+        } else {
+            let failure = self.gen_extract_failure(ty, slot_idx, quote! { err });
+            quote! {
+                if let Err(err) = #check {
+                    #failure;
+                }
+            }
+        }
+    }
+
     /// Generate the body for [`Self::gen_vm_fn()`] and [`Self::gen_vm_fn_constructor()`]
     fn gen_vm_fn_body(
         &self, source_name: &syn::Ident, constructor_mode: bool,
@@ -569,7 +1525,7 @@ impl WrenImplValidFn {
             .normal_params
             .iter()
             .map(|(idx, ty)| {
-                let slot_idx = idx + 1;
+                let slot_idx = self.slots[*idx];
                 let arg_name = syn::Ident::new(&format!("arg{}", idx), Span::call_site());
                 let arg_slot_name = syn::Ident::new(&format!("arg{}_calc", idx), Span::call_site());
                 let ty = &*ty.ty;
@@ -591,17 +1547,20 @@ impl WrenImplValidFn {
                         return Err(format!("failed to get value of type {} for slot {}", std::any::type_name::<#ty>(), #slot_idx));
                     }
                 } else {
-                    quote! {
-                        ruwren::foreign_v2::WrenTo::to_vm(format!("failed to get value of type {} for slot {}", std::any::type_name::<#ty>(), #slot_idx), vm, 0, 1);
-                        vm.abort_fiber(0);
-                        return
-                    }
+                    self.gen_extract_failure(ty, slot_idx, extract_failure_message(ty, slot_idx))
                 };
+                let validate = self.gen_validate(
+                    ty,
+                    slot_idx,
+                    quote! { ruwren::foreign_v2::check_slot_type::<#ty>(vm, #slot_idx) },
+                    constructor_mode,
+                );
                 (
                     (idx, quote! {
                         let #arg_slot_name = ruwren::foreign_v2::InputSlot::#call
                     }),
                     quote! {
+                        #validate
                         let Some(#arg_name): Option<#ty> = ruwren::foreign_v2::get_slot_value(vm, &#arg_slot_name, #arity) else {
                             #failure
                         }
@@ -615,7 +1574,7 @@ impl WrenImplValidFn {
         .map(|(idx, ty)| {
             use syn::visit::Visit;
 
-            let slot_idx = idx + 1;
+            let slot_idx = self.slots[*idx];
             let arg_name = syn::Ident::new(&format!("arg{}", idx), Span::call_site());
             let arg_slot_name = syn::Ident::new(&format!("arg{}_calc", idx), Span::call_site());
             let ty = &*ty.ty;
@@ -652,17 +1611,34 @@ impl WrenImplValidFn {
                     return Err(format!("failed to get value of type {} for slot {}", std::any::type_name::<#ty>(), #slot_idx));
                 }
             } else {
-                quote! {
-                    ruwren::foreign_v2::WrenTo::to_vm(format!("failed to get value of type {} for slot {}", std::any::type_name::<#ty>(), #slot_idx), vm, 0, 1);
-                    vm.abort_fiber(0);
-                    return
-                }
+                self.gen_extract_failure(ty, slot_idx, extract_failure_message(ty, slot_idx))
+            };
+            // Option<T> takes anything (and gets None for non-T values), Vec<Option<T>> a list
+            let expected = match ty {
+                syn::Type::Path(tp) => tp.path.segments.last().map(|seg| seg.ident.to_string()),
+                _ => None,
+            };
+            let validate = match expected.as_deref() {
+                Some("Option") => quote! {},
+                Some("Vec") => self.gen_validate(
+                    ty,
+                    slot_idx,
+                    quote! { ruwren::foreign_v2::check_slot_is(vm, #slot_idx, ruwren::SlotType::List) },
+                    constructor_mode,
+                ),
+                _ => self.gen_validate(
+                    ty,
+                    slot_idx,
+                    quote! { ruwren::foreign_v2::check_slot_is(vm, #slot_idx, ruwren::SlotType::Foreign) },
+                    constructor_mode,
+                ),
             };
             (
                 (idx, quote! {
                     let #arg_slot_name = ruwren::foreign_v2::InputSlot::#call
                 }),
                 quote! {
+                    #validate
                     let Some(#arg_name): Option<#ty> = ruwren::foreign_v2::get_slot_object::<#source_type, _>(vm, &#arg_slot_name, #arity, #receiver) else {
                         #failure
                     }
@@ -678,10 +1654,10 @@ impl WrenImplValidFn {
                 .map(|(i, d)| (i, d, true))
                 .chain(self.normal_params.iter().map(|(i, d)| (i, d, false)))
                 .collect();
-            call_args.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+            call_args.sort_by_key(|(a, _, _)| *a);
             let input_args = call_args.into_iter().map(|(idx, dat, is_obj)| {
                 let arg_name = syn::Ident::new(&format!("arg{}", idx), Span::call_site());
-                let slot_idx = idx + 1;
+                let slot_idx = self.slots[*idx];
                 let ty = &dat.ty;
                 if is_obj {
                     quote! {
@@ -701,6 +1677,8 @@ impl WrenImplValidFn {
                 }
             });
 
+            let input_args = input_args.chain(self.receive_vm.then(|| quote! { vm }));
+
             let class_name = generate_class_type_name(source_name);
             let wrapper_name = generate_wrapper_type_name(source_name);
             let name = &self.base_name();
@@ -717,7 +1695,7 @@ impl WrenImplValidFn {
         };
 
         let mut extractors: Vec<_> = normal_extract.into_iter().chain(object_extract).collect();
-        extractors.sort_by(|(a, _), (b, _)| a.cmp(b));
+        extractors.sort_by_key(|(a, _)| *a);
         let extractors: Vec<_> = extractors.into_iter().map(|(_, e)| e).collect();
 
         quote! {
@@ -743,11 +1721,18 @@ impl WrenImplValidFn {
         let instance_name = generate_instance_type_name(source_name);
         let vis = &self.func.vis;
         let body = self.gen_vm_fn_body(source_name, true);
+        let ret = match constructor_return(&self.func.sig.output, &instance_name) {
+            Some(ConstructorReturn::Instance) => quote! { Ok(ret) },
+            Some(ConstructorReturn::Option) => quote! {
+                ret.ok_or_else(|| "constructor returned None".to_string())
+            },
+            _ => quote! { ret.map_err(|err| err.to_string()) },
+        };
         quote! {
             #[inline]
             #vis fn #wrapper_fn_name(&mut self, vm: &ruwren::VM) -> Result<#instance_name, String> {
                 #body
-                ret
+                #ret
             }
         }
     }
@@ -758,11 +1743,55 @@ impl WrenImplValidFn {
         let wrapper_fn_name =
             syn::Ident::new(&format!("vm_{}", self.base_name()), Span::call_site());
         let body = self.gen_vm_fn_body(source_name, false);
+        // A Result aborts the fiber with its error's Display text, like a constructor's does
+        let result_ok = match &self.func.sig.output {
+            ReturnType::Type(_, ty) => result_ok_type(ty),
+            ReturnType::Default => None,
+        };
+        // Check the return type up front, so a missing WrenTo impl is reported on it
+        // rather than deep inside the generated body
+        let checked_ty = match &self.func.sig.output {
+            ReturnType::Type(_, ty) if !matches!(**ty, Type::ImplTrait(_)) => match result_ok {
+                Some(ok) => ok,
+                None => Some(&**ty),
+            },
+            _ => None,
+        };
+        let return_check = match checked_ty {
+            Some(ty) => quote_spanned! {ty.span()=>
+                {
+                    fn returns_wren_to<T: ruwren::foreign_v2::WrenTo>() {}
+                    returns_wren_to::<#ty>();
+                }
+            },
+            None => quote! {},
+        };
+        let send = if result_ok.is_some() {
+            quote! {
+                match ret {
+                    Ok(ret) => ruwren::foreign_v2::WrenTo::to_vm(ret, vm, 0, 1),
+                    Err(err) => {
+                        vm.set_slot_string(0, err.to_string());
+                        vm.abort_fiber(0);
+                    }
+                }
+            }
+        } else {
+            quote! {
+                ruwren::foreign_v2::WrenTo::to_vm(ret, vm, 0, 1);
+            }
+        };
+        let receiver = if self.shared_class {
+            quote! { &self }
+        } else {
+            quote! { &mut self }
+        };
         quote_spanned! {self.func.span()=>
             #[inline(always)]
-            fn #wrapper_fn_name(&mut self, vm: &ruwren::VM) {
+            fn #wrapper_fn_name(#receiver, vm: &ruwren::VM) {
+                #return_check
                 #body
-                ruwren::foreign_v2::WrenTo::to_vm(ret, vm, 0, 1);
+                #send
             }
         }
     }
@@ -774,7 +1803,12 @@ impl WrenImplValidFn {
     /// this wrapper calls.
     ///
     /// This wrapper function is FFI-safe. (or at least, should be)
-    fn gen_native_vm_fn(&self, source_name: &syn::Ident) -> proc_macro2::TokenStream {
+    ///
+    /// When `quiet_panics` is set, the panic hook is swapped out for the duration of the call.
+    /// [`CodegenVersion::V2`] never touches the panic hook, so it ignores `quiet_panics`.
+    fn gen_native_vm_fn(
+        &self, source_name: &syn::Ident, quiet_panics: bool, version: CodegenVersion,
+    ) -> proc_macro2::TokenStream {
         let wrapper_fn = self.gen_vm_fn(source_name);
         let wrapper_fn_name =
             syn::Ident::new(&format!("vm_{}", self.base_name()), Span::call_site());
@@ -786,10 +1820,69 @@ impl WrenImplValidFn {
         let class_name = generate_class_type_name(source_name);
         let wrapper_name = generate_wrapper_type_name(source_name);
         let vis = &self.func.vis;
-        let native_wrapper = if self.is_static {
+        let (set_hook, take_hook) = gen_panic_hook(quiet_panics);
+        let use_class = if self.shared_class {
+            quote! { use_class }
+        } else {
+            quote! { use_class_mut }
+        };
+        let native_wrapper = if version == CodegenVersion::V2 {
+            let method_name = self.source_name().to_string();
+            let call = if self.is_static {
+                quote! {
+                    #class_name::#wrapper_fn_name(class, vm)
+                }
+            } else {
+                quote! {
+                    vm.ensure_slots(1);
+                    let inst = vm
+                        .get_slot_foreign_mut::<#instance_name>(0)
+                        .unwrap_or_else(|| panic!(
+                            "Tried to call {0} of {1} on non-{1} type",
+                            #method_name,
+                            std::any::type_name::<#instance_name>()
+                        ));
+                    let mut wrapper: #wrapper_name = (class, inst).into();
+                    wrapper.#wrapper_fn_name(vm)
+                }
+            };
+            quote! {
+                #vis unsafe extern "C" fn #native_name(vm: *mut ruwren::wren_sys::WrenVM) {
+                    use std::panic::AssertUnwindSafe;
+                    use ruwren::handle_panic as catch_unwind;
+
+                    let conf = std::ptr::read_unaligned(
+                        ruwren::wren_sys::wrenGetUserData(vm) as *mut ruwren::UserData
+                    );
+                    let ovm = vm;
+                    let vm = std::rc::Weak::upgrade(&conf.vm)
+                        .unwrap_or_else(|| panic!("Failed to access VM at {:p}", &conf.vm));
+                    {
+                        use ruwren::foreign_v2::V2Class;
+                        let vm_borrow = vm.borrow();
+                        let res = catch_unwind(AssertUnwindSafe(|| {
+                            vm_borrow.#use_class::<#instance_name, _, _>(|vm, cls| {
+                                let class = cls.unwrap_or_else(|| {
+                                    panic!("Failed to resolve class for {}", #class_name::name())
+                                });
+                                #call
+                            })
+                        }));
+                        if let Err(err) = res {
+                            vm_borrow.set_slot_string(0, ruwren::panic_message(err));
+                            vm_borrow.abort_fiber(0);
+                        }
+                    }
+                    std::ptr::write_unaligned(
+                        ruwren::wren_sys::wrenGetUserData(ovm) as *mut ruwren::UserData,
+                        conf,
+                    );
+                }
+            }
+        } else if self.is_static {
             quote! {
                 #vis unsafe extern "C" fn #native_name(vm: *mut ruwren::wren_sys::WrenVM) {
-                    use std::panic::{set_hook, take_hook, AssertUnwindSafe};
+                    use std::panic::AssertUnwindSafe;
 
                     let conf = std::ptr::read_unaligned(
                         ruwren::wren_sys::wrenGetUserData(vm) as *mut ruwren::UserData
@@ -797,17 +1890,17 @@ impl WrenImplValidFn {
                     let ovm = vm;
                     let vm = std::rc::Weak::upgrade(&conf.vm)
                         .unwrap_or_else(|| panic!("Failed to access VM at {:p}", &conf.vm));
-                    set_hook(Box::new(|_| {}));
+                    #set_hook
                     let vm_borrow = AssertUnwindSafe(vm.borrow());
                     {
                         use ruwren::foreign_v2::V2Class;
-                        vm_borrow.use_class_mut::<#instance_name, _, _>(|vm, cls| {
+                        vm_borrow.#use_class::<#instance_name, _, _>(|vm, cls| {
                             let class =
                                 cls.unwrap_or_else(|| panic!("Failed to resolve class for {}", #class_name::name()));
                             #class_name::#wrapper_fn_name(class, vm)
                         })
                     };
-                    drop(take_hook());
+                    #take_hook
                     std::ptr::write_unaligned(
                         ruwren::wren_sys::wrenGetUserData(ovm) as *mut ruwren::UserData,
                         conf,
@@ -817,7 +1910,7 @@ impl WrenImplValidFn {
         } else {
             quote! {
                 #vis unsafe extern "C" fn #native_name(vm: *mut ruwren::wren_sys::WrenVM) {
-                    use std::panic::{set_hook, take_hook, AssertUnwindSafe};
+                    use std::panic::AssertUnwindSafe;
 
                     let conf = std::ptr::read_unaligned(
                         ruwren::wren_sys::wrenGetUserData(vm) as *mut ruwren::UserData
@@ -825,7 +1918,7 @@ impl WrenImplValidFn {
                     let ovm = vm;
                     let vm = std::rc::Weak::upgrade(&conf.vm)
                         .unwrap_or_else(|| panic!("Failed to access VM at {:p}", &conf.vm));
-                    set_hook(Box::new(|_pi| {}));
+                    #set_hook
                     let vm_borrow = AssertUnwindSafe(vm.borrow());
                     {
                         use ruwren::foreign_v2::V2Class;
@@ -844,7 +1937,7 @@ impl WrenImplValidFn {
                             wrapper.#wrapper_fn_name(vm)
                         })
                     };
-                    drop(take_hook());
+                    #take_hook
                     std::ptr::write_unaligned(
                         ruwren::wren_sys::wrenGetUserData(ovm) as *mut ruwren::UserData,
                         conf,
@@ -864,13 +1957,38 @@ impl WrenImplValidFn {
 struct WrenImplFn {
     func: ImplItemFn,
     attrs: WrenImplFnAttrs,
+    // Whether the function had any #[wren_impl(...)] attribute at all
+    annotated: bool,
 }
 
 impl TryFrom<(&syn::Ident, WrenImplFn)> for WrenImplValidFn {
     type Error = Vec<String>;
 
-    fn try_from((src, value): (&syn::Ident, WrenImplFn)) -> Result<Self, Self::Error> {
-        let (receiver_ty, args, has_self): (syn::Type, _, _) =
+    fn try_from((src, mut value): (&syn::Ident, WrenImplFn)) -> Result<Self, Self::Error> {
+        if value.attrs.to_string {
+            if value.attrs.setter
+                || value.attrs.subscript_getter
+                || value.attrs.subscript_setter
+                || value.attrs.rename.is_some()
+                || value.attrs.operator.is_some()
+            {
+                return Err(vec![format!(
+                    "to_string {} is registered as toString, so it can't be a setter, subscript, operator or renamed",
+                    value.func.sig.ident
+                )]);
+            }
+            if value.func.sig.output != parse_quote! { -> String } {
+                return Err(vec![format!(
+                    "to_string {} must return String",
+                    value.func.sig.ident
+                )]);
+            }
+            value.attrs.instance = true;
+            value.attrs.getter = true;
+            value.attrs.rename = Some("toString".to_string());
+        }
+
+        let (receiver_ty, mut args, has_self): (syn::Type, _, _) =
             if value.func.sig.receiver().is_some() {
                 let class_type = generate_class_type_name(src);
                 let wrapper_type = generate_wrapper_type_name(src);
@@ -905,10 +2023,23 @@ impl TryFrom<(&syn::Ident, WrenImplFn)> for WrenImplValidFn {
                 ((*arg.ty).clone(), inputs, false)
             };
 
+        if value.attrs.receive_vm {
+            match args.pop().map(|p| p.into_value()) {
+                Some(syn::FnArg::Typed(_)) => {}
+                _ => {
+                    return Err(vec![format!(
+                        "method {} must take the VM as its last parameter to use receive_vm",
+                        value.func.sig.ident
+                    )])
+                }
+            }
+        }
+
         let object_param_pairs: Vec<_> = value
             .attrs
             .object
             .iter()
+            .chain(value.attrs.object_slot.iter().map(|decl| &decl.name))
             .map(|name| {
                 (
                     name,
@@ -932,7 +2063,14 @@ impl TryFrom<(&syn::Ident, WrenImplFn)> for WrenImplValidFn {
             .cloned()
             .enumerate()
             .partition(|(_, arg)| match &*arg.pat {
-                syn::Pat::Ident(i) => value.attrs.object.contains(&i.ident),
+                syn::Pat::Ident(i) => {
+                    value.attrs.object.contains(&i.ident)
+                        || value
+                            .attrs
+                            .object_slot
+                            .iter()
+                            .any(|decl| decl.name == i.ident)
+                }
                 _ => false,
             });
 
@@ -947,6 +2085,48 @@ impl TryFrom<(&syn::Ident, WrenImplFn)> for WrenImplValidFn {
             })
             .collect();
 
+        // Explicitly placed objects claim their slots first, everything else fills the rest in order
+        let param_count = object_params.len() + normal_params.len();
+        let mut explicit_slots = vec![None; param_count];
+        for decl in &value.attrs.object_slot {
+            if decl.slot == 0 || decl.slot > param_count {
+                errors.push(format!(
+                    "object_slot for {} must be between 1 and {} (the arity of {}), but got {}",
+                    decl.name, param_count, value.func.sig.ident, decl.slot
+                ));
+            } else if explicit_slots.contains(&Some(decl.slot)) {
+                errors.push(format!(
+                    "object_slot {} is used more than once in {}",
+                    decl.slot, value.func.sig.ident
+                ));
+            } else if let Some((idx, _)) = object_params.iter().find(|(_, arg)| match &*arg.pat {
+                syn::Pat::Ident(i) => i.ident == decl.name,
+                _ => false,
+            }) {
+                explicit_slots[*idx] = Some(decl.slot);
+            }
+        }
+        let mut free_slots =
+            (1..=param_count).filter(|slot| !explicit_slots.contains(&Some(*slot)));
+        let slots: Vec<_> = explicit_slots
+            .iter()
+            .map(|slot| slot.or_else(|| free_slots.next()).unwrap_or_default())
+            .collect();
+
+        let on_error = match &value.attrs.on_error {
+            Some(handler) => match syn::parse_str::<syn::Path>(handler) {
+                Ok(handler) => Some(handler),
+                Err(_) => {
+                    errors.push(format!(
+                        "on_error must be a function path, but got \"{}\"",
+                        handler
+                    ));
+                    None
+                }
+            },
+            None => None,
+        };
+
         let mut given_name = None;
 
         let is_setter = if value.attrs.setter {
@@ -996,9 +2176,88 @@ impl TryFrom<(&syn::Ident, WrenImplFn)> for WrenImplValidFn {
             false
         };
 
+        let is_subscript_getter = if value.attrs.subscript_getter {
+            let count = if has_self { 2 } else { 1 };
+            if args.len() == count {
+                true
+            } else {
+                errors.push(format!(
+                    "subscript getter {} must take 1 non-receiver argument (takes {} arguments)",
+                    value.func.sig.ident,
+                    args.len(),
+                ));
+                false
+            }
+        } else {
+            false
+        };
+
+        let is_subscript_setter = if value.attrs.subscript_setter {
+            let output = &value.func.sig.output;
+            let count = if has_self { 3 } else { 2 };
+            if args.len() == count
+                && (*output == syn::ReturnType::Default || *output == parse_quote! { -> ()})
+            {
+                true
+            } else {
+                errors.push(format!(
+                    "subscript setter {} must take 2 non-receiver arguments (takes {} arguments), and return () (returns {})",
+                    value.func.sig.ident,
+                    args.len(),
+                    match output {
+                        syn::ReturnType::Default => parse_quote!{()},
+                        syn::ReturnType::Type(_, ty) => ty.into_token_stream(),
+                    }
+                ));
+                false
+            }
+        } else {
+            false
+        };
+
+        if let Some(operator) = &value.attrs.operator {
+            let arity = normal_params.len() + object_params.len();
+            match operator_arities(operator) {
+                None => errors.push(format!(
+                    "{} isn't an operator Wren lets classes overload",
+                    operator
+                )),
+                Some(arities) if !arities.contains(&arity) => errors.push(format!(
+                    "operator {} ({}) must take {} non-receiver arguments (takes {} arguments)",
+                    operator,
+                    value.func.sig.ident,
+                    arities
+                        .iter()
+                        .map(|a| a.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" or "),
+                    arity,
+                )),
+                Some(_) => {}
+            }
+            if value.attrs.getter
+                || value.attrs.setter
+                || value.attrs.subscript_getter
+                || value.attrs.subscript_setter
+                || value.attrs.rename.is_some()
+            {
+                errors.push(format!(
+                    "operator {} ({}) already decides the Wren signature, so it can't be a getter, setter, subscript or renamed",
+                    operator, value.func.sig.ident,
+                ));
+            }
+        }
+
         if !errors.is_empty() {
             Err(errors)
         } else {
+            let shared_class = !value.attrs.instance
+                && object_params.is_empty()
+                && value
+                    .func
+                    .sig
+                    .receiver()
+                    .is_some_and(|r| r.reference.is_some() && r.mutability.is_none());
             let mut func = value.func;
             let source_name = if let Some(given_name) = given_name {
                 let source_name = func.sig.ident.clone();
@@ -1011,24 +2270,56 @@ impl TryFrom<(&syn::Ident, WrenImplFn)> for WrenImplValidFn {
                 receiver_ty,
                 is_getter,
                 is_setter,
+                is_subscript_getter,
+                is_subscript_setter,
                 source_name,
                 is_static: !value.attrs.instance,
                 func,
                 normal_params,
                 object_params,
+                slots,
+                on_error,
+                receive_vm: value.attrs.receive_vm,
+                rename: value.attrs.rename,
+                operator: value.attrs.operator,
+                validate_inputs: false,
+                shared_class,
             })
         }
     }
 }
 
 impl WrenImplFn {
+    fn validate_finalizer(&self) -> Result<(), Vec<String>> {
+        let takes_mut_self = matches!(
+            self.func.sig.inputs.first(),
+            Some(syn::FnArg::Receiver(r)) if r.reference.is_some() && r.mutability.is_some()
+        );
+        let returns_unit = self.func.sig.output == ReturnType::Default
+            || self.func.sig.output == parse_quote! { -> () };
+        if takes_mut_self && self.func.sig.inputs.len() == 1 && returns_unit {
+            Ok(())
+        } else {
+            Err(vec![format!(
+                "finalizer {} must take only &mut self, and return ()",
+                self.func.sig.ident
+            )])
+        }
+    }
+
     fn validate_allocator(&mut self, ty: &syn::Ident) -> Result<(), Vec<String>> {
         let class_ty = generate_class_type_name(ty);
 
         let mut errors = vec![];
 
-        if !self.func.sig.inputs.is_empty() {
-            errors.push("allocators cannot take any parameters".to_string());
+        // The VM is the only thing an allocator can be given
+        let takes_vm = match self.func.sig.inputs.first() {
+            None => true,
+            Some(syn::FnArg::Typed(_)) => self.func.sig.inputs.len() == 1,
+            Some(syn::FnArg::Receiver(_)) => false,
+        };
+        if !takes_vm {
+            errors.push("allocators can only take the VM (vm: &VM) as a parameter".to_string());
         }
 
         match self.func.sig.output {
@@ -1072,8 +2363,16 @@ impl Parse for WrenImplFn {
         let item: ImplItem = input.parse()?;
         match item {
             ImplItem::Fn(mut func) => {
+                let annotated = func
+                    .attrs
+                    .iter()
+                    .any(|attr| attr.path().is_ident("wren_impl"));
                 let attrs = deluxe::extract_attributes(&mut func)?;
-                Ok(Self { func, attrs })
+                Ok(Self {
+                    func,
+                    attrs,
+                    annotated,
+                })
             }
             _ => unimplemented!(),
         }
@@ -1083,31 +2382,98 @@ impl Parse for WrenImplFn {
 struct WrenObjectImpl {
     ty: syn::Ident,
     items: Vec<WrenImplFn>,
+    // Treat every function without a #[wren_impl(...)] attribute as ignored
+    ignore_unannotated: bool,
+}
+
+/// How a constructor hands back its instance
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ConstructorReturn {
+    /// `-> FooInstance`, it can't fail
+    Instance,
+    /// `-> Option<FooInstance>`, failing with "constructor returned None"
+    Option,
+    /// `-> Result<FooInstance, E>`, failing with E's Display text
+    Result,
+}
+
+/// How `output` returns `instance_name`, if it's one of the forms a constructor can use
+fn constructor_return(
+    output: &ReturnType, instance_name: &syn::Ident,
+) -> Option<ConstructorReturn> {
+    if returns_instance_result(output, instance_name) {
+        return Some(ConstructorReturn::Result);
+    }
+    let ReturnType::Type(_, ty) = output else {
+        return None;
+    };
+    if **ty == parse_quote! { #instance_name } {
+        Some(ConstructorReturn::Instance)
+    } else if **ty == parse_quote! { Option<#instance_name> } {
+        Some(ConstructorReturn::Option)
+    } else {
+        None
+    }
+}
+
+/// Is `output` `-> Result<instance_name, E>`, for some E
+fn returns_instance_result(output: &ReturnType, instance_name: &syn::Ident) -> bool {
+    let ReturnType::Type(_, ty) = output else {
+        return false;
+    };
+    let Type::Path(tp) = ty.as_ref() else {
+        return false;
+    };
+    let Some(last) = tp.path.segments.last() else {
+        return false;
+    };
+    let syn::PathArguments::AngleBracketed(args) = &last.arguments else {
+        return false;
+    };
+    let types: Vec<_> = args
+        .args
+        .iter()
+        .filter_map(|a| match a {
+            syn::GenericArgument::Type(t) => Some(t),
+            _ => None,
+        })
+        .collect();
+    last.ident == "Result" && types.len() == 2 && *types[0] == parse_quote! { #instance_name }
 }
 
 struct WrenObjectValidImpl {
     ty: syn::Ident,
     allocator: Option<WrenImplFn>,
     constructor: Option<WrenImplValidFn>,
+    finalizer: Option<WrenImplFn>,
+    error_type: Option<syn::Path>,
+    pass_through: Vec<WrenImplFn>,
     others: Vec<WrenImplValidFn>,
 }
 
 impl WrenObjectImpl {
-    fn validate(self) -> Result<WrenObjectValidImpl, Vec<String>> {
-        let allocators: Vec<_> = self.items.iter().filter(|fi| fi.attrs.allocator).collect();
+    fn validate(mut self) -> Result<WrenObjectValidImpl, Vec<syn::Error>> {
+        if self.ignore_unannotated {
+            for item in self.items.iter_mut().filter(|fi| !fi.annotated) {
+                item.attrs.ignore = true;
+            }
+        }
+
+        let allocators: Vec<_> = self.items.iter().filter(|fi| fi.attrs.allocator).collect();
         let constructors: Vec<_> = self
             .items
             .iter()
             .filter(|fi| fi.attrs.constructor)
             .collect();
         let mut errors = vec![];
+        let mut spanned_errors = vec![];
 
         let mut allocator = if allocators.len() <= 1 {
             allocators.first().cloned().cloned()
         } else {
-            return Err(vec![format!(
-                "Expected 0 or 1 allocators, found {}",
-                allocators.len()
+            return Err(vec![syn::Error::new(
+                Span::call_site(),
+                format!("Expected 0 or 1 allocators, found {}", allocators.len()),
             )]);
         };
 
@@ -1120,12 +2486,45 @@ impl WrenObjectImpl {
         let constructor = if constructors.len() <= 1 {
             constructors.first().cloned().cloned()
         } else {
-            return Err(vec![format!(
-                "Expected 0 or 1 constructors, found {}",
-                constructors.len()
+            return Err(vec![syn::Error::new(
+                Span::call_site(),
+                format!("Expected 0 or 1 constructors, found {}", constructors.len()),
+            )]);
+        };
+
+        let finalizers: Vec<_> = self.items.iter().filter(|fi| fi.attrs.finalizer).collect();
+        let finalizer = if finalizers.len() <= 1 {
+            finalizers.first().cloned().cloned()
+        } else {
+            return Err(vec![syn::Error::new(
+                Span::call_site(),
+                format!("Expected 0 or 1 finalizers, found {}", finalizers.len()),
             )]);
         };
 
+        if let Some(ref finalizer) = finalizer {
+            if let Err(errs) = finalizer.validate_finalizer() {
+                errors.extend(errs)
+            }
+        }
+
+        let error_type = match constructor
+            .as_ref()
+            .and_then(|c| c.attrs.error_type.as_ref())
+        {
+            Some(path) => match syn::parse_str::<syn::Path>(path) {
+                Ok(path) => Some(path),
+                Err(_) => {
+                    errors.push(format!(
+                        "error_type must be a Rust type path, but got \"{}\"",
+                        path
+                    ));
+                    None
+                }
+            },
+            None => None,
+        };
+
         let constructor = if let Some(constructor) = constructor {
             let instance_name = generate_instance_type_name(&self.ty);
             let class_name = generate_class_type_name(&self.ty);
@@ -1143,9 +2542,7 @@ impl WrenObjectImpl {
                             }
                         }
                     }
-                    if constructor.func.sig.output
-                        == parse_quote! {-> Result<#instance_name, String>}
-                    {
+                    if constructor_return(&constructor.func.sig.output, &instance_name).is_some() {
                         if match constructor.receiver_ty {
                             Type::Reference(ref tr) => tr.elem == parse_quote! { #class_name },
                             Type::Path(ref tp) => tp.path == parse_quote! { #class_name },
@@ -1153,17 +2550,33 @@ impl WrenObjectImpl {
                         } {
                             Some(constructor)
                         } else {
-                            errors.push(format!(
-                                "A constructor must receive &mut {0} (or &{0}), but it receives {1}",
-                                class_name.into_token_stream(),
-                                constructor.receiver_ty.into_token_stream(),
+                            let takes = match &constructor.receiver_ty {
+                                Type::Reference(tr) => format!(
+                                    "&{}{}",
+                                    if tr.mutability.is_some() { "mut " } else { "" },
+                                    tr.elem.to_token_stream()
+                                ),
+                                ty => ty.to_token_stream().to_string(),
+                            };
+                            // Point at the type of the parameter standing in for the receiver
+                            let receiver: &dyn ToTokens = match constructor.func.sig.inputs.first()
+                            {
+                                Some(syn::FnArg::Typed(arg)) => &arg.ty,
+                                _ => &constructor.func.sig,
+                            };
+                            spanned_errors.push(syn::Error::new_spanned(
+                                receiver,
+                                format!(
+                                    "the constructor for `{0}` must take `&mut {1}` (or `&{1}`, the allocator's output) as its first argument, but it takes `{2}`",
+                                    self.ty, class_name, takes,
+                                ),
                             ));
                             None
                         }
                     } else {
                         errors.push(format!(
-                            "A constructor must return {}, but it returns {}",
-                            quote! { Result<#instance_name, String> },
+                            "A constructor must return {0}, Option<{0}> or Result<{0}, E> (where E: Display), but it returns {1}",
+                            instance_name,
                             constructor.func.sig.output.into_token_stream(),
                         ));
                         None
@@ -1178,10 +2591,59 @@ impl WrenObjectImpl {
             None
         };
 
+        errors.extend(
+            self.items
+                .iter()
+                .filter(|fi| !fi.attrs.constructor && fi.attrs.error_type.is_some())
+                .map(|fi| {
+                    format!(
+                        "error_type is only valid on a constructor, but was given to {}",
+                        fi.func.sig.ident
+                    )
+                }),
+        );
+
+        errors.extend(
+            self.items
+                .iter()
+                .filter(|fi| fi.attrs.constructor && fi.attrs.on_error.is_some())
+                .map(|fi| {
+                    format!(
+                        "on_error is not valid on a constructor (which reports errors through its return value), but was given to {}",
+                        fi.func.sig.ident
+                    )
+                }),
+        );
+
+        errors.extend(
+            self.items
+                .iter()
+                .filter(|fi| fi.attrs.pass_through && (fi.attrs.constructor || fi.attrs.allocator))
+                .map(|fi| {
+                    format!(
+                        "pass_through can't be used on an allocator or constructor, but was given to {}",
+                        fi.func.sig.ident
+                    )
+                }),
+        );
+
+        let pass_through: Vec<_> = self
+            .items
+            .iter()
+            .filter(|fi| fi.attrs.pass_through && !fi.attrs.ignore)
+            .cloned()
+            .collect();
+
         let others: Vec<_> = self
             .items
             .iter()
-            .filter(|fi| !fi.attrs.ignore && !fi.attrs.constructor && !fi.attrs.allocator)
+            .filter(|fi| {
+                !fi.attrs.ignore
+                    && !fi.attrs.pass_through
+                    && !fi.attrs.constructor
+                    && !fi.attrs.allocator
+                    && !fi.attrs.finalizer
+            })
             .cloned()
             .filter_map(|func| -> Option<WrenImplValidFn> {
                 match (&self.ty, func).try_into() {
@@ -1194,13 +2656,20 @@ impl WrenObjectImpl {
             })
             .collect();
 
-        if !errors.is_empty() {
-            Err(errors)
+        if !errors.is_empty() || !spanned_errors.is_empty() {
+            Err(errors
+                .into_iter()
+                .map(|err| syn::Error::new(Span::call_site(), err))
+                .chain(spanned_errors)
+                .collect())
         } else {
             Ok(WrenObjectValidImpl {
                 ty: self.ty,
                 allocator,
                 constructor,
+                finalizer,
+                error_type,
+                pass_through,
                 others,
             })
         }
@@ -1210,30 +2679,121 @@ impl WrenObjectImpl {
 impl Parse for WrenObjectImpl {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         input.parse::<Token![impl]>()?;
+        // Wren keeps foreign objects alive for as long as it likes, and classes are looked up by
+        // TypeId, so each foreign class is one concrete 'static type. Generic structs name their
+        // instantiations with #[wren(concrete(...))], and wren_impl goes on those
+        if input.peek(Token![<]) {
+            let generics: syn::Generics = input.parse()?;
+            return Err(syn::Error::new_spanned(
+                generics,
+                "wren_impl can't support generic or lifetime parameters: foreign classes are looked up by TypeId, so each one is a single 'static type",
+            ));
+        }
         let ty = input.parse()?;
+        if input.peek(Token![<]) {
+            let args: syn::AngleBracketedGenericArguments = input.parse()?;
+            return Err(syn::Error::new_spanned(
+                args,
+                "wren_impl needs a concrete type, name this instantiation with #[wren(concrete(...))] and implement that instead",
+            ));
+        }
         let content;
         braced!(content in input);
         let mut items = vec![];
         while !content.is_empty() {
             items.push(content.parse()?);
         }
-        Ok(Self { ty, items })
+        Ok(Self {
+            ty,
+            items,
+            ignore_unannotated: false,
+        })
+    }
+}
+
+#[derive(Default, deluxe::ParseMetaItem)]
+#[deluxe(default)]
+struct WrenImplAttrs {
+    // Leave the panic hook alone in the generated wrappers
+    no_panic_hook: bool,
+    // Check every argument's slot type before extracting it
+    validate_inputs: bool,
+    // Which style of glue code to generate, "1" (the default) or "2"
+    version: Option<String>,
+    // A fn() -> FooClass used to allocate the class, instead of going through Foo's Default
+    class_default: Option<String>,
+    // Only add methods to a class whose main block is marked extended
+    extend: bool,
+    // Also register the methods from this type's extend block
+    extended: bool,
+    // Only expose functions with a #[wren_impl(...)] attribute, ignoring the rest
+    ignore_unannotated: bool,
+}
+
+/// The style of glue code generated by [`wren_impl`]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CodegenVersion {
+    /// Silences the panic hook around each call
+    V1,
+    /// Catches panics with `catch_unwind` and reports them to Wren,
+    /// without touching the panic hook
+    V2,
+}
+
+/// Generate the statements that silence the panic hook around a foreign call,
+/// and restore it afterwards
+fn gen_panic_hook(quiet_panics: bool) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    if quiet_panics {
+        (
+            quote! { std::panic::set_hook(Box::new(|_| {})); },
+            quote! { drop(std::panic::take_hook()); },
+        )
+    } else {
+        (quote! {}, quote! {})
     }
 }
 
+/// Generates the glue that makes `Foo` (a `#[derive(WrenObject)]` type) a foreign class.
+///
+/// A type can only have one main block, but methods can be spread over a second block marked
+/// `#[wren_impl(extend)]` (only methods, no allocator, constructor or finalizer). The main block
+/// is then marked `#[wren_impl(extended)]`, so that it registers the extend block's methods too.
 #[proc_macro_attribute]
 pub fn wren_impl(
-    _attr: proc_macro::TokenStream, item: proc_macro::TokenStream,
+    attr: proc_macro::TokenStream, item: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    let wren_object_impl = parse_macro_input!(item as WrenObjectImpl);
+    let mut wren_object_impl = parse_macro_input!(item as WrenObjectImpl);
 
     let errors = deluxe::Errors::new();
 
-    let wren_object_impl = match wren_object_impl.validate() {
+    let impl_attrs = match deluxe::parse::<WrenImplAttrs>(attr) {
+        Ok(attrs) => attrs,
+        Err(e) => {
+            errors.push_syn(e);
+            WrenImplAttrs::default()
+        }
+    };
+
+    let version = match impl_attrs.version.as_deref() {
+        None | Some("1") => CodegenVersion::V1,
+        Some("2") => CodegenVersion::V2,
+        Some(other) => {
+            errors.push_call_site(format!(
+                "version must be \"1\" or \"2\", but got \"{}\"",
+                other
+            ));
+            CodegenVersion::V1
+        }
+    };
+    let quiet_panics = !impl_attrs.no_panic_hook && version == CodegenVersion::V1;
+    wren_object_impl.ignore_unannotated = impl_attrs.ignore_unannotated;
+    let (set_hook, take_hook) = gen_panic_hook(quiet_panics);
+
+    let mut wren_object_impl = match wren_object_impl.validate() {
         Ok(valid) => valid,
         Err(errs) => {
             for err in errs {
-                errors.push_call_site(err)
+                errors.push_syn(err)
             }
             return quote! {
                 #errors
@@ -1242,6 +2802,49 @@ pub fn wren_impl(
         }
     };
 
+    if impl_attrs.validate_inputs {
+        for func in wren_object_impl
+            .constructor
+            .iter_mut()
+            .chain(wren_object_impl.others.iter_mut())
+        {
+            func.validate_inputs = true;
+        }
+    }
+
+    if impl_attrs.extend {
+        if impl_attrs.extended {
+            errors.push_call_site("a block can't be both extend and extended");
+        }
+        if impl_attrs.class_default.is_some()
+            || wren_object_impl.allocator.is_some()
+            || wren_object_impl.constructor.is_some()
+            || wren_object_impl.finalizer.is_some()
+        {
+            errors.push_call_site(
+                "an extend block only adds methods, the allocator, constructor, finalizer and class_default belong in the main block",
+            );
+        }
+    }
+
+    let class_default = match &impl_attrs.class_default {
+        Some(_) if wren_object_impl.allocator.is_some() => {
+            errors.push_call_site("class_default can't be used alongside an allocator");
+            None
+        }
+        Some(class_default) => match syn::parse_str::<syn::Path>(class_default) {
+            Ok(class_default) => Some(class_default),
+            Err(_) => {
+                errors.push_call_site(format!(
+                    "class_default must be a function path, but got \"{}\"",
+                    class_default
+                ));
+                None
+            }
+        },
+        None => None,
+    };
+
     let source_ty = &wren_object_impl.ty;
     let instance_ty = generate_instance_type_name(source_ty);
     let class_ty = generate_class_type_name(source_ty);
@@ -1254,6 +2857,7 @@ pub fn wren_impl(
                 #func
             }
         }
+        None if class_default.is_some() => quote! {},
         None => quote! {
             #[inline]
             fn ___default_alloc() -> #class_ty {
@@ -1281,18 +2885,26 @@ pub fn wren_impl(
         },
     };
 
+    let allocator_vm = match &wren_object_impl.allocator {
+        Some(alloc) if !alloc.func.sig.inputs.is_empty() => quote! { vm },
+        _ => quote! { _vm },
+    };
     let allocator_call = match &wren_object_impl.allocator {
         Some(alloc) => {
             let name = &alloc.func.sig.ident;
+            let vm = (!alloc.func.sig.inputs.is_empty()).then(|| quote! { vm });
             quote! {
-                #class_ty::#name()
+                #class_ty::#name(#vm)
             }
         }
-        None => {
-            quote! {
+        None => match &class_default {
+            Some(class_default) => quote! {
+                #class_default()
+            },
+            None => quote! {
                 #class_ty::___default_alloc()
-            }
-        }
+            },
+        },
     };
 
     let constructor_call = match &wren_object_impl.constructor {
@@ -1309,26 +2921,49 @@ pub fn wren_impl(
             }
         }
     };
+    let constructor_error = match &wren_object_impl.error_type {
+        // Slot 0 is the only one we're sure exists here, and growing the slots would corrupt
+        // the constructor call, so the error's class is loaded into slot 0 and replaced by the error
+        Some(error_type) => quote_spanned! {error_type.span()=>
+            let error = <#error_type as From<String>>::from(err_string.clone());
+            if let Err(send_err) =
+                ruwren::foreign_v2::SendForeign::send_foreign(error, &*vm_borrow, 0, 0)
+            {
+                vm_borrow.set_slot_string(
+                    0,
+                    format!("{} (and the error object couldn't be created: {})", err_string, send_err),
+                );
+            }
+            vm_borrow.abort_fiber(0);
+        },
+        None => quote! {
+            vm_borrow.set_slot_string(0, err_string);
+            vm_borrow.abort_fiber(0);
+        },
+    };
+
+    let create_call = match version {
+        CodegenVersion::V1 => quote! { #instance_ty::create(&*vm_borrow) },
+        CodegenVersion::V2 => quote! {
+            match catch_unwind(AssertUnwindSafe(|| #instance_ty::create(&*vm_borrow))) {
+                Ok(res) => res,
+                Err(err) => Err(ruwren::panic_message(err)),
+            }
+        },
+    };
+
     let function_decls = wren_object_impl.others.iter().map(|func| {
-        let name = func.source_name();
         let wrapper_name = syn::Ident::new(
             &format!("native_vm_{}", func.base_name()),
             Span::call_site(),
         );
         let is_static = func.is_static;
-        let arity = func.arity();
         let receiver_ty = if func.is_static {
             &class_ty
         } else {
             &wrapper_ty
         };
-        let sig = if func.is_getter {
-            quote! { ruwren::FunctionSignature::new_getter(stringify!(#name)) }
-        } else if func.is_setter {
-            quote! { ruwren::FunctionSignature::new_setter(stringify!(#name)) }
-        } else {
-            quote! { ruwren::FunctionSignature::new_function(stringify!(#name), #arity) }
-        };
+        let sig = func.gen_signature();
         quote! {
             ruwren::MethodPointer {
                 is_static: #is_static,
@@ -1343,7 +2978,7 @@ pub fn wren_impl(
         .iter()
         .filter(|of| of.is_static)
         .map(|func| {
-            let wrapper_func = func.gen_native_vm_fn(source_ty);
+            let wrapper_func = func.gen_native_vm_fn(source_ty, quiet_panics, version);
             let func = &func.func;
             quote_spanned! {func.span()=>
                 #func
@@ -1356,7 +2991,7 @@ pub fn wren_impl(
         .iter()
         .filter(|of| !of.is_static)
         .map(|func| {
-            let wrapper_func = func.gen_native_vm_fn(source_ty);
+            let wrapper_func = func.gen_native_vm_fn(source_ty, quiet_panics, version);
             let func = &func.func;
             quote_spanned! {func.span()=>
                 #func
@@ -1364,6 +2999,144 @@ pub fn wren_impl(
             }
         });
 
+    let (finalizer_fn, finalizer_call) = match &wren_object_impl.finalizer {
+        Some(finalizer) => {
+            let func = &finalizer.func;
+            let name = &func.sig.ident;
+            (
+                quote_spanned! {func.span()=>
+                    impl #instance_ty {
+                        #func
+                    }
+                },
+                quote! {
+                    if let Some(instance) = fo.object_mut() {
+                        instance.#name();
+                    }
+                },
+            )
+        }
+        None => (quote! {}, quote! {}),
+    };
+
+    let (instance_pass_through, static_pass_through): (Vec<_>, Vec<_>) = wren_object_impl
+        .pass_through
+        .iter()
+        .partition(|fi| fi.attrs.instance);
+    let instance_pass_through = instance_pass_through.into_iter().map(|fi| &fi.func);
+    let static_pass_through = static_pass_through.into_iter().map(|fi| &fi.func);
+
+    // Parameter names for the Wren stubs, matched up with the registered methods at runtime
+    let declared_params = wren_object_impl.others.iter().map(|func| {
+        let is_static = func.is_static;
+        let sig = func.gen_signature();
+        let params = func.wren_param_names();
+        quote! {
+            (#is_static, #sig, &[#(#params),*] as &'static [&'static str])
+        }
+    });
+    let find_params = quote! {
+        let declared: Vec<(bool, ruwren::FunctionSignature, &'static [&'static str])> = vec![
+            #(
+                #declared_params
+            ),*
+        ];
+        declared
+            .into_iter()
+            .find(|(declared_static, declared_sig, _)| {
+                *declared_static == is_static && declared_sig == signature
+            })
+            .map(|(_, _, params)| params)
+    };
+
+    if impl_attrs.extend {
+        let extra_params = if cfg!(feature = "stubs") {
+            quote! {
+                impl #instance_ty {
+                    #[doc(hidden)]
+                    pub fn extra_method_params(
+                        is_static: bool, signature: &ruwren::FunctionSignature,
+                    ) -> Option<&'static [&'static str]> {
+                        #find_params
+                    }
+                }
+            }
+        } else {
+            quote! {}
+        };
+        let expanded = quote! {
+            #errors
+            #extra_params
+            impl #class_ty {
+                #(
+                    #static_fns
+                )*
+                #(
+                    #static_pass_through
+                )*
+            }
+
+            impl<'a> #wrapper_ty<'a> {
+                #(
+                    #instance_fns
+                )*
+                #(
+                    #instance_pass_through
+                )*
+            }
+
+            impl #instance_ty {
+                #[doc(hidden)]
+                pub fn extra_pointers() -> Vec<ruwren::MethodPointer> {
+                    vec![
+                        #(
+                            #function_decls
+                        ),*
+                    ]
+                }
+            }
+        };
+        trace_expansion("wren_impl", &expanded);
+        return proc_macro::TokenStream::from(expanded);
+    }
+
+    let extra_pointers = if impl_attrs.extended {
+        quote! { .chain(#instance_ty::extra_pointers()) }
+    } else {
+        quote! {}
+    };
+
+    let stub_impl = if cfg!(feature = "stubs") {
+        let constructor_params = wren_object_impl
+            .constructor
+            .as_ref()
+            .map(|constructor| constructor.wren_param_names())
+            .unwrap_or_default();
+        let extra_params = if impl_attrs.extended {
+            quote! { .or_else(|| #instance_ty::extra_method_params(is_static, signature)) }
+        } else {
+            quote! {}
+        };
+        quote! {
+            impl ruwren::foreign_v2::WrenStub for #instance_ty {
+                fn constructor_params() -> &'static [&'static str] {
+                    &[#(#constructor_params),*]
+                }
+
+                fn method_params(
+                    is_static: bool, signature: &ruwren::FunctionSignature,
+                ) -> Option<&'static [&'static str]> {
+                    let found = {
+                        #find_params
+                    };
+                    found #extra_params
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
     let expanded = quote! {
         #errors
         impl #class_ty {
@@ -1372,14 +3145,22 @@ pub fn wren_impl(
             #(
                 #static_fns
             )*
+            #(
+                #static_pass_through
+            )*
         }
 
         impl<'a> #wrapper_ty<'a> {
             #(
                 #instance_fns
             )*
+            #(
+                #instance_pass_through
+            )*
         }
 
+        #finalizer_fn
+
         impl ruwren::foreign_v2::Slottable<#source_ty> for #instance_ty {
             type Context = #class_ty;
             #[inline]
@@ -1407,7 +3188,7 @@ pub fn wren_impl(
             {
                 extern "C" fn _constructor(vm: *mut ruwren::wren_sys::WrenVM) {
                     use ruwren::foreign_v2::ForeignItem;
-                    use std::panic::{set_hook, take_hook, AssertUnwindSafe};
+                    use std::panic::AssertUnwindSafe;
                     use ruwren::handle_panic as catch_unwind;
                     unsafe {
                         let ud = ruwren::wren_sys::wrenGetUserData(vm);
@@ -1416,9 +3197,9 @@ pub fn wren_impl(
                         let vm = std::rc::Weak::upgrade(&conf.vm)
                             .unwrap_or_else(|| panic!("Failed to access VM at {:p}", &conf.vm));
                         // Allocate a new object, and move it onto the heap
-                        set_hook(Box::new(|_pi| {}));
+                        #set_hook
                         let vm_borrow = AssertUnwindSafe(vm.borrow());
-                        match #instance_ty::create(&*vm_borrow)
+                        match #create_call
                         {
                             Ok(object) => {
                                 let wptr = ruwren::wren_sys::wrenSetSlotNewForeign(
@@ -1430,18 +3211,14 @@ pub fn wren_impl(
 
                                 std::ptr::write(
                                     wptr as *mut _,
-                                    ruwren::ForeignObject {
-                                        object: Box::into_raw(Box::new(object)),
-                                        type_id: std::any::TypeId::of::<#instance_ty>(),
-                                    },
+                                    ruwren::ForeignObject::new(&*vm_borrow, object),
                                 );
                             },
                             Err(err_string) => {
-                                vm_borrow.set_slot_string(0, err_string);
-                                vm_borrow.abort_fiber(0);
+                                #constructor_error
                             }
                         };
-                        drop(take_hook());
+                        #take_hook
                         std::ptr::write_unaligned(
                             ud as *mut ruwren::UserData,
                             conf
@@ -1460,10 +3237,8 @@ pub fn wren_impl(
                     unsafe {
                         let mut fo: ruwren::ForeignObject<#instance_ty> =
                             std::ptr::read_unaligned(data as *mut _);
-                        if !fo.object.is_null() {
-                            _ = Box::from_raw(fo.object);
-                        }
-                        fo.object = std::ptr::null_mut();
+                        #finalizer_call
+                        fo.finalize();
                         std::ptr::write_unaligned(data as *mut _, fo);
                     }
                 }
@@ -1475,12 +3250,18 @@ pub fn wren_impl(
             where
                 Self: Sized,
             {
+                use ruwren::foreign_v2::DerivedItems;
+                let declared = [
+                    #(
+                        #function_decls
+                    ),*
+                ];
                 ruwren::ClassObjectPointers {
-                    function_pointers: vec![
-                        #(
-                            #function_decls
-                        ),*
-                    ]
+                    function_pointers: declared
+                        .into_iter()
+                        .chain(#source_ty::derived_methods())
+                        #extra_pointers
+                        .collect(),
                 }
             }
         }
@@ -1488,15 +3269,18 @@ pub fn wren_impl(
         impl ruwren::foreign_v2::V2Class for #class_ty {
             #[inline]
             fn name() -> &'static str {
-                stringify!(#source_ty)
+                use ruwren::foreign_v2::DerivedItems;
+                #source_ty::WREN_NAME.unwrap_or(stringify!(#source_ty))
             }
 
             #[inline]
-            fn allocate() -> Self {
+            fn allocate(#allocator_vm: &ruwren::VM) -> Self {
                 #allocator_call
             }
         }
 
+        #stub_impl
+
         impl ruwren::foreign_v2::ForeignItem for #instance_ty {
             type Class = #class_ty;
             type Source = #source_ty;
@@ -1507,24 +3291,232 @@ pub fn wren_impl(
             }
         }
     };
-    println!("--- wren_impl -----------------------------");
-    writeln!(std::io::stdout(), "{}", expanded);
+    trace_expansion("wren_impl", &expanded);
+    proc_macro::TokenStream::from(expanded)
+}
+
+/// Stands in for [`wren_impl`] (on the same impl block) when unit testing.
+///
+/// No VM glue is generated: every function is emitted as-is onto `FooClass` and `FooWrapper`,
+/// and a `FooMock` (holding a `FooClass` and a `FooInstance`) is generated that records the
+/// name of every method called through it. Each method's result can be replaced with
+/// `mock_<method>(closure)`, otherwise the real implementation runs.
+///
+/// Methods marked `receive_vm` need a VM, so they are left out of the mock.
+#[proc_macro_attribute]
+pub fn wren_mock(
+    _attr: proc_macro::TokenStream, item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let wren_object_impl = parse_macro_input!(item as WrenObjectImpl);
+
+    let errors = deluxe::Errors::new();
+
+    let wren_object_impl = match wren_object_impl.validate() {
+        Ok(valid) => valid,
+        Err(errs) => {
+            for err in errs {
+                errors.push_syn(err)
+            }
+            return quote! {
+                #errors
+            }
+            .into();
+        }
+    };
+
+    let source_ty = &wren_object_impl.ty;
+    let instance_ty = generate_instance_type_name(source_ty);
+    let class_ty = generate_class_type_name(source_ty);
+    let wrapper_ty = generate_wrapper_type_name(source_ty);
+    let mock_ty = syn::Ident::new(&format!("{}Mock", source_ty), source_ty.span());
+
+    let allocator_fn = wren_object_impl.allocator.as_ref().map(|alloc| &alloc.func);
+    let constructor_fn = wren_object_impl.constructor.as_ref().map(|c| &c.func);
+    let finalizer_fn = wren_object_impl.finalizer.as_ref().map(|finalizer| {
+        let func = &finalizer.func;
+        quote! {
+            impl #instance_ty {
+                #func
+            }
+        }
+    });
+    let static_fns = wren_object_impl
+        .others
+        .iter()
+        .filter(|of| of.is_static)
+        .map(|of| &of.func)
+        .chain(
+            wren_object_impl
+                .pass_through
+                .iter()
+                .filter(|fi| !fi.attrs.instance)
+                .map(|fi| &fi.func),
+        );
+    let instance_fns = wren_object_impl
+        .others
+        .iter()
+        .filter(|of| !of.is_static)
+        .map(|of| &of.func)
+        .chain(
+            wren_object_impl
+                .pass_through
+                .iter()
+                .filter(|fi| fi.attrs.instance)
+                .map(|fi| &fi.func),
+        );
+
+    let mocked: Vec<_> = wren_object_impl
+        .others
+        .iter()
+        .filter(|of| !of.receive_vm)
+        .collect();
+    let (mock_fields, mock_methods): (Vec<_>, Vec<_>) = mocked
+        .iter()
+        .map(|of| {
+            let name = of.base_name();
+            let name_str = name.to_string();
+            let mock_name = syn::Ident::new(&format!("mock_{}", name), Span::call_site());
+            let mut params: Vec<_> = of.normal_params.iter().chain(&of.object_params).collect();
+            params.sort_by_key(|(idx, _)| *idx);
+            let arg_names: Vec<_> = params
+                .iter()
+                .map(|(idx, _)| syn::Ident::new(&format!("arg{}", idx), Span::call_site()))
+                .collect();
+            let arg_tys: Vec<_> = params.iter().map(|(_, pt)| &pt.ty).collect();
+            let ret = match &of.func.sig.output {
+                ReturnType::Default => quote! { () },
+                ReturnType::Type(_, ty) => quote! { #ty },
+            };
+            let real_call = if of.is_static {
+                quote! { #class_ty::#name(&mut self.class, #(#arg_names),*) }
+            } else {
+                quote! {
+                    let mut wrapper: #wrapper_ty = (&mut self.class, &mut self.instance).into();
+                    #wrapper_ty::#name(&mut wrapper, #(#arg_names),*)
+                }
+            };
+            (
+                quote! {
+                    #mock_name: Option<Box<dyn FnMut(#(#arg_tys),*) -> #ret>>
+                },
+                quote! {
+                    /// Replace the result of this method
+                    pub fn #mock_name<F: FnMut(#(#arg_tys),*) -> #ret + 'static>(&mut self, f: F) -> &mut Self {
+                        self.#mock_name = Some(Box::new(f));
+                        self
+                    }
+
+                    pub fn #name(&mut self, #(#arg_names: #arg_tys),*) -> #ret {
+                        self.calls.push(#name_str);
+                        if let Some(mock) = self.#mock_name.as_mut() {
+                            return mock(#(#arg_names),*);
+                        }
+                        #real_call
+                    }
+                },
+            )
+        })
+        .unzip();
+    let mock_names = mocked
+        .iter()
+        .map(|of| syn::Ident::new(&format!("mock_{}", of.base_name()), Span::call_site()));
+
+    let expanded = quote! {
+        #errors
+        impl #class_ty {
+            #allocator_fn
+            #constructor_fn
+            #(
+                #static_fns
+            )*
+        }
+
+        impl<'a> #wrapper_ty<'a> {
+            #(
+                #instance_fns
+            )*
+        }
+
+        #finalizer_fn
+
+        #[allow(dead_code)]
+        pub struct #mock_ty {
+            pub class: #class_ty,
+            pub instance: #instance_ty,
+            calls: Vec<&'static str>,
+            #(
+                #mock_fields,
+            )*
+        }
+
+        #[allow(dead_code)]
+        impl #mock_ty {
+            pub fn new(class: #class_ty, instance: #instance_ty) -> Self {
+                Self {
+                    class,
+                    instance,
+                    calls: vec![],
+                    #(
+                        #mock_names: None,
+                    )*
+                }
+            }
+
+            /// The methods called through this mock, in order
+            pub fn calls(&self) -> &[&'static str] {
+                &self.calls
+            }
+
+            #(
+                #mock_methods
+            )*
+        }
+    };
+
+    trace_expansion("wren_mock", &expanded);
     proc_macro::TokenStream::from(expanded)
 }
 
 struct WrenModuleItem {
+    // Registered with #[wren_module(secondary)], because another module already
+    // generates the type's WrenTo impl (and the type can only have one)
+    secondary: bool,
+    // Given to the type's register_<name> function (trait impls can't have a visibility,
+    // so the generated ones are exactly as reachable as the type itself)
+    vis: syn::Visibility,
     ty: syn::TypePath,
 }
 
 impl Parse for WrenModuleItem {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        input.parse::<Token![pub]>()?;
+        let mut secondary = false;
+        for attr in input.call(syn::Attribute::parse_outer)? {
+            if !attr.path().is_ident("wren_module") {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "expected #[wren_module(secondary)]",
+                ));
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("secondary") {
+                    secondary = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected secondary"))
+                }
+            })?;
+        }
+        let vis = input.parse()?;
         let ty = input.parse()?;
-        Ok(Self { ty })
+        Ok(Self { secondary, vis, ty })
     }
 }
 
 struct WrenModuleDecl {
+    // Where write_stubs() puts the module's Wren stubs, under OUT_DIR
+    stub_path: Option<syn::LitStr>,
+    // The Wren module name, if it isn't the Rust one with underscores turned into slashes
+    module: Option<syn::LitStr>,
     vis: syn::Visibility,
     name: syn::Ident,
     items: Punctuated<WrenModuleItem, Token![;]>,
@@ -1532,20 +3524,83 @@ struct WrenModuleDecl {
 
 impl Parse for WrenModuleDecl {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut stub_path = None;
+        let mut module = None;
+        while input.peek(syn::Ident) && input.peek2(Token![=]) {
+            let key: syn::Ident = input.parse()?;
+            let setting = if key == "stub_path" {
+                &mut stub_path
+            } else if key == "module" {
+                &mut module
+            } else {
+                return Err(syn::Error::new_spanned(
+                    key,
+                    "expected stub_path = \"...\", module = \"...\", or a module",
+                ));
+            };
+            if setting.is_some() {
+                return Err(syn::Error::new_spanned(
+                    &key,
+                    format!("{} is given more than once", key),
+                ));
+            }
+            input.parse::<Token![=]>()?;
+            *setting = Some(input.parse()?);
+            input.parse::<Token![,]>()?;
+        }
         let vis = input.parse()?;
         input.parse::<Token![mod]>()?;
         let name: syn::Ident = input.parse()?;
         let content;
         braced!(content in input);
         let items = content.parse_terminated(WrenModuleItem::parse, Token![;])?;
-        Ok(Self { vis, name, items })
+        Ok(Self {
+            stub_path,
+            module,
+            vis,
+            name,
+            items,
+        })
     }
 }
 
+/// Declares a Wren module of `#[derive(WrenObject)]` types, with a `publish_module(lib)` function.
+///
+/// The Wren module is named after the Rust one, with underscores turned into slashes
+/// (`mod game_math` is `"game/math"`), unless it's given with a leading `module = "...",`.
+///
+/// Each listed type also gets a `WrenTo` impl, which sends it as an instance of this module's class.
+/// A type listed in several modules has to be marked `#[wren_module(secondary)]` in all but one.
+///
+/// Each one is also added by its own `register_<name>` function (`register_foo_bar` for
+/// `FooBar`), which gets the visibility the type is listed with: `pub(crate) FooBar;` keeps
+/// it from being registered outside the crate, and `FooBar;` outside the module. The generated
+/// impls can't have a visibility, so they're exactly as reachable as the type itself.
 #[proc_macro]
 pub fn wren_module(stream: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let wren_module_decl = parse_macro_input!(stream as WrenModuleDecl);
 
+    let expanded = generate_module(wren_module_decl, false);
+
+    trace_expansion("wren_module", &expanded);
+    proc_macro::TokenStream::from(expanded)
+}
+
+/// Like [`wren_module!`], but builds the module with a `ruwren::ModuleBuilder`,
+/// which is also exposed as `module_builder()` so it can be merged with others.
+#[proc_macro]
+pub fn wren_module_v2(stream: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let wren_module_decl = parse_macro_input!(stream as WrenModuleDecl);
+
+    let expanded = generate_module(wren_module_decl, true);
+
+    trace_expansion("wren_module_v2", &expanded);
+    proc_macro::TokenStream::from(expanded)
+}
+
+fn generate_module(
+    wren_module_decl: WrenModuleDecl, use_builder: bool,
+) -> proc_macro2::TokenStream {
     let vis = wren_module_decl.vis;
     let name = wren_module_decl.name;
     let (decls, to_impls): (Vec<_>, Vec<_>) = wren_module_decl
@@ -1555,43 +3610,148 @@ pub fn wren_module(stream: proc_macro::TokenStream) -> proc_macro::TokenStream {
             let source_ty = &mi.ty;
             let class_ty = generate_class_type(source_ty);
             let instance_ty = generate_instance_type(source_ty);
-            (
-                quote_spanned! {mi.ty.span()=>
-                    module.class::<#instance_ty, _>(#class_ty::name());
-                },
+            let class_object_check = quote_spanned! {mi.ty.span()=>
+                assert_class_object::<#instance_ty>();
+            };
+            let (add_class, module_ty) = if use_builder {
+                (quote! { add_class }, quote! { ruwren::ModuleBuilder })
+            } else {
+                (quote! { class }, quote! { ruwren::Module })
+            };
+            let item_vis = &mi.vis;
+            let register_fn = generate_register_fn_name(source_ty);
+            let register_doc = format!(
+                "Adds `{}` to `module`, as it's published by this one",
+                quote!(#source_ty).to_string().replace(' ', "")
+            );
+            let to_impl = if mi.secondary {
+                quote! {}
+            } else {
                 quote! {
-                    impl ruwren::foreign_v2::WrenTo for #source_ty {
-                        const SCRATCH_SPACE: usize = 1;
+                    impl ruwren::foreign_v2::SendForeign for #source_ty {
                         #[inline]
-                        fn to_vm(self, vm: &ruwren::VM, slot: ruwren::SlotId, scratch_start: ruwren::SlotId) {
+                        fn send_foreign(
+                            self, vm: &ruwren::VM, slot: ruwren::SlotId, scratch: ruwren::SlotId,
+                        ) -> Result<(), ruwren::ForeignSendError> {
                             vm.set_slot_new_foreign_scratch::<_, _, #instance_ty>(
                                 module_name(),
                                 #class_ty::name(),
                                 self.into(),
                                 slot,
-                                scratch_start,
+                                scratch,
                             )
-                            .unwrap();
+                            .map(|_| ())
                         }
                     }
+
+                    impl ruwren::foreign_v2::WrenTo for #source_ty {
+                        const SCRATCH_SPACE: usize = 1;
+                        #[inline]
+                        fn to_vm(self, vm: &ruwren::VM, slot: ruwren::SlotId, scratch_start: ruwren::SlotId) {
+                            ruwren::foreign_v2::SendForeign::send_foreign(self, vm, slot, scratch_start)
+                                .unwrap();
+                        }
+                    }
+                }
+            };
+            (
+                quote! {
+                    #register_fn(&mut module);
+                },
+                quote! {
+                    // Point at the listed type if it's missing #[derive(WrenObject)]/#[wren_impl]
+                    const _: fn() = || {
+                        fn assert_class_object<T: ruwren::ClassObject>() {}
+                        #class_object_check
+                    };
+
+                    #[doc = #register_doc]
+                    #[inline]
+                    #item_vis fn #register_fn(module: &mut #module_ty) {
+                        module.#add_class::<#instance_ty, _>(#class_ty::name());
+                    }
+
+                    #to_impl
                 },
             )
         })
         .unzip();
 
-    let expanded = quote! {
-        #vis mod #name {
-            use ruwren::foreign_v2::V2Class;
+    let stub_classes = wren_module_decl.items.iter().map(|mi| {
+        let class_ty = generate_class_type(&mi.ty);
+        let instance_ty = generate_instance_type(&mi.ty);
+        quote! {
+            ruwren::foreign_v2::class_stub::<#instance_ty>(#class_ty::name())
+        }
+    });
+    let stubs = if cfg!(feature = "stubs") {
+        let write_stubs = wren_module_decl.stub_path.as_ref().map(|stub_path| {
+            quote! {
+                /// Write [`wren_stubs`] to `$OUT_DIR/<stub_path>/<module name>.wren`,
+                /// from a build script
+                pub fn write_stubs() -> std::io::Result<std::path::PathBuf> {
+                    let out_dir = std::env::var_os("OUT_DIR").ok_or_else(|| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::NotFound,
+                            "OUT_DIR isn't set, write_stubs should be called from a build script",
+                        )
+                    })?;
+                    let path = std::path::Path::new(&out_dir)
+                        .join(#stub_path)
+                        .join(format!("{}.wren", module_name()));
+                    if let Some(dir) = path.parent() {
+                        std::fs::create_dir_all(dir)?;
+                    }
+                    std::fs::write(&path, wren_stubs())?;
+                    Ok(path)
+                }
+            }
+        });
+        quote! {
+            /// Wren declarations for this module's foreign classes,
+            /// with parameter names taken from the Rust methods
+            pub fn wren_stubs() -> String {
+                let stubs: Vec<String> = vec![
+                    #(
+                        #stub_classes
+                    ),*
+                ];
+                stubs.join("\n")
+            }
 
+            #write_stubs
+        }
+    } else {
+        match &wren_module_decl.stub_path {
+            Some(stub_path) => quote_spanned! {stub_path.span()=>
+                compile_error!("stub_path needs ruwren's stubs feature");
+            },
+            None => quote! {},
+        }
+    };
+
+    let publish = if use_builder {
+        quote! {
             #[inline]
-            fn module_name() -> String {
-                stringify!(#name).replace("_", "/")
-            }
+            pub fn module_builder() -> ruwren::ModuleBuilder {
+                let mut module = ruwren::ModuleBuilder::new();
 
-            #(
-                #to_impls
-            )*
+                {
+                    #(
+                        #decls
+                    )*
+                }
 
+                module
+            }
+
+            #[inline]
+            pub fn publish_module(lib: &mut ruwren::ModuleLibrary) {
+                lib.module(module_name(), module_builder().build());
+            }
+        }
+    } else {
+        quote! {
             #[inline]
             pub fn publish_module(lib: &mut ruwren::ModuleLibrary) {
                 let mut module = ruwren::Module::new();
@@ -1606,8 +3766,180 @@ pub fn wren_module(stream: proc_macro::TokenStream) -> proc_macro::TokenStream {
             }
         }
     };
-    
-    println!("--- wren_module -----------------------------");
-    writeln!(std::io::stdout(), "{}", expanded);
+
+    let module_name = match &wren_module_decl.module {
+        Some(module) => quote! { #module.to_string() },
+        None => quote! { stringify!(#name).replace("_", "/") },
+    };
+
+    quote! {
+        #vis mod #name {
+            use ruwren::foreign_v2::V2Class;
+
+            #[inline]
+            fn module_name() -> String {
+                #module_name
+            }
+
+            #(
+                #to_impls
+            )*
+
+            #publish
+
+            #stubs
+        }
+    }
+}
+
+struct WrenTestSource {
+    module: syn::LitStr,
+    source: syn::Expr,
+}
+
+impl Parse for WrenTestSource {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let module = input.parse()?;
+        input.parse::<Token![=>]>()?;
+        let source = input.parse()?;
+        Ok(Self { module, source })
+    }
+}
+
+struct WrenTestDecl {
+    name: syn::Ident,
+    modules: Vec<syn::Path>,
+    // Module sources (usually foreign class declarations) to interpret before `source`
+    sources: Vec<WrenTestSource>,
+    source: syn::Expr,
+    expect_error: Option<syn::Expr>,
+}
+
+impl Parse for WrenTestDecl {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let span = input.span();
+        let mut name = None;
+        let mut modules = None;
+        let mut sources = None;
+        let mut source = None;
+        let mut expect_error = None;
+        while !input.is_empty() {
+            let key: syn::Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            let duplicate = if key == "name" {
+                name.replace(input.parse()?).is_some()
+            } else if key == "modules" {
+                let content;
+                syn::bracketed!(content in input);
+                let paths = content.parse_terminated(syn::Path::parse, Token![,])?;
+                modules.replace(paths.into_iter().collect()).is_some()
+            } else if key == "sources" {
+                let content;
+                syn::bracketed!(content in input);
+                let pairs = content.parse_terminated(WrenTestSource::parse, Token![,])?;
+                sources.replace(pairs.into_iter().collect()).is_some()
+            } else if key == "source" {
+                source.replace(input.parse()?).is_some()
+            } else if key == "expect_error" {
+                expect_error.replace(input.parse()?).is_some()
+            } else {
+                return Err(syn::Error::new_spanned(
+                    key,
+                    "expected name, modules, sources, source, or expect_error",
+                ));
+            };
+            if duplicate {
+                return Err(syn::Error::new_spanned(
+                    &key,
+                    format!("{} is given more than once", key),
+                ));
+            }
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+        }
+        Ok(Self {
+            name: name.ok_or_else(|| syn::Error::new(span, "wren_test! needs a name = ..."))?,
+            modules: modules.unwrap_or_default(),
+            sources: sources.unwrap_or_default(),
+            source: source
+                .ok_or_else(|| syn::Error::new(span, "wren_test! needs a source = ..."))?,
+            expect_error,
+        })
+    }
+}
+
+/// Declares a `#[test]` that publishes `modules` (declared with [`wren_module!`]) to a fresh VM,
+/// interprets each of `sources` into its module, then interprets `source` as `"main"`.
+///
+/// The test passes if `source` runs without error, or with `expect_error = "..."`,
+/// if it fails with an error (compile or runtime) that contains the given text.
+///
+/// ```ignore
+/// wren_test! {
+///     name = adds_numbers,
+///     modules = [math],
+///     sources = ["math" => "class Math {\n foreign static add(a, b)\n }"],
+///     source = r#"
+///         import "math" for Math
+///         if (Math.add(1, 2) != 3) Fiber.abort("wrong sum")
+///     "#,
+/// }
+/// ```
+#[proc_macro]
+pub fn wren_test(stream: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let WrenTestDecl {
+        name,
+        modules,
+        sources,
+        source,
+        expect_error,
+    } = parse_macro_input!(stream as WrenTestDecl);
+
+    let loads = sources.iter().map(|WrenTestSource { module, source }| {
+        quote! {
+            if let Err(err) = vm.interpret(#module, #source) {
+                panic!("failed to interpret module {}: {}", #module, err);
+            }
+        }
+    });
+    let check = match expect_error {
+        Some(expected) => quote! {
+            let expected: &str = #expected;
+            match result {
+                Ok(()) => panic!("expected an error containing {:?}, but the script ran", expected),
+                Err(err) => assert!(
+                    err.to_string().contains(expected),
+                    "expected an error containing {:?}, got: {}",
+                    expected,
+                    err
+                ),
+            }
+        },
+        None => quote! {
+            if let Err(err) = result {
+                panic!("{}", err);
+            }
+        },
+    };
+
+    let expanded = quote! {
+        #[test]
+        fn #name() {
+            let mut lib = ruwren::ModuleLibrary::new();
+            #(
+                #modules::publish_module(&mut lib);
+            )*
+            let vm = ruwren::VMConfig::new().library(&lib).build();
+            #(
+                #loads
+            )*
+            let result = vm.interpret("main", #source);
+            #check
+        }
+    };
+
+    trace_expansion("wren_test", &expanded);
     proc_macro::TokenStream::from(expanded)
 }
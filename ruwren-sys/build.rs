@@ -26,7 +26,7 @@ fn main() {
         .include("include")
         .include("optional")
         .include("src")
-        .std("c99")
+        .std("c11") // C11 for <stdatomic.h>, see patches/interrupt.patch
         .warnings(false) // We don't control the source...
         .compile("wren");
 
@@ -324,6 +324,14 @@ WREN_API void wrenCollectGarbage(WrenVM* vm);
 WREN_API WrenInterpretResult wrenInterpret(WrenVM* vm, const char* module,
                                   const char* source);
 
+// Compiles [source], a single expression, in the context of resolved [module]
+// into a function that evaluates it. Call it with a "call()" handle.
+//
+// Returns NULL if [source] doesn't compile, after reporting the error.
+// Otherwise, the returned handle must be released with [wrenReleaseHandle].
+WREN_API WrenHandle* wrenCompileExpression(WrenVM* vm, const char* module,
+                                           const char* source);
+
 // Creates a handle that can be used to invoke a method with [signature] on
 // using a receiver and arguments that are set up on the stack.
 //
@@ -490,6 +498,9 @@ WREN_API void wrenSetSlotString(WrenVM* vm, int slot, const char* text);
 // This does not release the handle for the value.
 WREN_API void wrenSetSlotHandle(WrenVM* vm, int slot, WrenHandle* handle);
 
+// Stores the value in [srcSlot] in [dstSlot] as well.
+WREN_API void wrenCopySlot(WrenVM* vm, int dstSlot, int srcSlot);
+
 // Returns the number of elements in the list stored in [slot].
 WREN_API int wrenGetListCount(WrenVM* vm, int slot);
 
@@ -551,4 +562,9 @@ WREN_API void* wrenGetUserData(WrenVM* vm);
 // Sets user data associated with the WrenVM.
 WREN_API void wrenSetUserData(WrenVM* vm, void* userData);
 
+// Asks the running interpreter to stop. The current fiber (and any fiber that
+// catches its error) aborts at its next loop or method call. Safe to call from
+// another thread. The request is cleared when the next interpret or call starts.
+WREN_API void wrenInterrupt(WrenVM* vm);
+
 #endif
@@ -67,6 +67,7 @@ WrenVM* wrenNewVM(WrenConfiguration* config)
   
   WrenVM* vm = (WrenVM*)reallocate(NULL, sizeof(*vm), userData);
   memset(vm, 0, sizeof(WrenVM));
+  atomic_init(&vm->interruptRequested, false);
 
   // Copy the configuration if given one.
   if (config != NULL)
@@ -1034,6 +1035,12 @@ static WrenInterpretResult runInterpreter(WrenVM* vm, register ObjFiber* fiber)
       goto completeCall;
 
     completeCall:
+      if (atomic_load_explicit(&vm->interruptRequested, memory_order_relaxed))
+      {
+        fiber->error = CONST_STRING(vm, "Interrupted by the host.");
+        RUNTIME_ERROR();
+      }
+
       // If the class's method table doesn't include the symbol, bail.
       if (symbol >= classObj->methods.count ||
           (method = &classObj->methods.data[symbol])->type == METHOD_NONE)
@@ -1158,6 +1165,11 @@ static WrenInterpretResult runInterpreter(WrenVM* vm, register ObjFiber* fiber)
       // Jump back to the top of the loop.
       uint16_t offset = READ_SHORT();
       ip -= offset;
+      if (atomic_load_explicit(&vm->interruptRequested, memory_order_relaxed))
+      {
+        fiber->error = CONST_STRING(vm, "Interrupted by the host.");
+        RUNTIME_ERROR();
+      }
       DISPATCH();
     }
 
@@ -1458,6 +1470,9 @@ WrenInterpretResult wrenCall(WrenVM* vm, WrenHandle* method)
   // can call foreign methods from within calls to wrenCall().
   vm->apiStack = NULL;
 
+  // A new call, so an interrupt meant for an earlier one doesn't apply.
+  atomic_store_explicit(&vm->interruptRequested, false, memory_order_relaxed);
+
   // Discard any extra temporary slots. We take for granted that the stub
   // function has exactly one slot for each argument.
   vm->fiber->stackTop = &vm->fiber->stack[closure->fn->maxSlots];
@@ -1521,9 +1536,24 @@ WrenInterpretResult wrenInterpret(WrenVM* vm, const char* module,
   wrenPopRoot(vm); // closure.
   vm->apiStack = NULL;
 
+  // A new interpret, so an interrupt meant for an earlier one doesn't apply.
+  atomic_store_explicit(&vm->interruptRequested, false, memory_order_relaxed);
+
   return runInterpreter(vm, fiber);
 }
 
+WrenHandle* wrenCompileExpression(WrenVM* vm, const char* module,
+                                  const char* source)
+{
+  ObjClosure* closure = wrenCompileSource(vm, module, source, true, true);
+  if (closure == NULL) return NULL;
+
+  wrenPushRoot(vm, (Obj*)closure);
+  WrenHandle* handle = wrenMakeHandle(vm, OBJ_VAL(closure));
+  wrenPopRoot(vm); // closure.
+  return handle;
+}
+
 ObjClosure* wrenCompileSource(WrenVM* vm, const char* module, const char* source,
                             bool isExpression, bool printErrors)
 {
@@ -1789,6 +1819,12 @@ void wrenSetSlotHandle(WrenVM* vm, int slot, WrenHandle* handle)
   setSlot(vm, slot, handle->value);
 }
 
+void wrenCopySlot(WrenVM* vm, int dstSlot, int srcSlot)
+{
+  validateApiSlot(vm, srcSlot);
+  setSlot(vm, dstSlot, vm->apiStack[srcSlot]);
+}
+
 int wrenGetListCount(WrenVM* vm, int slot)
 {
   validateApiSlot(vm, slot);
@@ -1991,3 +2027,8 @@ void wrenSetUserData(WrenVM* vm, void* userData)
 {
 	vm->config.userData = userData;
 }
+
+void wrenInterrupt(WrenVM* vm)
+{
+  atomic_store_explicit(&vm->interruptRequested, true, memory_order_relaxed);
+}
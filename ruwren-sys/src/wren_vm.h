@@ -1,6 +1,8 @@
 #ifndef wren_vm_h
 #define wren_vm_h
 
+#include <stdatomic.h>
+
 #include "wren_common.h"
 #include "wren_compiler.h"
 #include "wren_value.h"
@@ -45,6 +47,11 @@ struct WrenVM
   // The fiber that is currently running.
   ObjFiber* fiber;
 
+  // Set by [wrenInterrupt], possibly from another thread. While set, the
+  // interpreter raises a runtime error at every loop and call. Only a flag, so
+  // relaxed loads and stores are enough.
+  atomic_bool interruptRequested;
+
   // The loaded modules. Each key is an ObjString (except for the main module,
   // whose key is null) for the module's name and the value is the ObjModule
   // for the module.
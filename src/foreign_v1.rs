@@ -53,10 +53,7 @@ macro_rules! create_module {
                         drop(take_hook());
                         // Copy the object pointer if we were successful
                         if let Some(object) = object {
-                            std::ptr::write(wptr as *mut _, $crate::ForeignObject {
-                                object: Box::into_raw(Box::new(object)),
-                                type_id: std::any::TypeId::of::<$name>(),
-                            });
+                            std::ptr::write(wptr as *mut _, $crate::ForeignObject::new(&*vm_borrow, object));
                         }
                         std::ptr::write_unaligned($crate::wren_sys::wrenGetUserData(ovm) as *mut $crate::UserData, conf);
                     }
@@ -65,10 +62,7 @@ macro_rules! create_module {
                 pub(in super) extern "C" fn _destructor(data: *mut std::ffi::c_void) {
                     unsafe {
                         let mut fo: $crate::ForeignObject<$name> = std::ptr::read_unaligned(data as *mut _);
-                        if !fo.object.is_null() {
-                            _ = Box::from_raw(fo.object);
-                        }
-                        fo.object = std::ptr::null_mut();
+                        fo.finalize();
                         std::ptr::write_unaligned(data as *mut _, fo);
                     }
                 }
@@ -78,16 +72,18 @@ macro_rules! create_module {
                 )*
             }
 
+            impl $crate::foreign_v2::WrenForeignType for $name {}
+
             impl $crate::ClassObject for $name {
                 fn initialize_pointer() -> extern "C" fn(*mut $crate::wren_sys::WrenVM) { $md::_constructor }
                 fn finalize_pointer() -> extern "C" fn(*mut std::ffi::c_void) { $md::_destructor }
                 fn generate_pointers() -> $crate::ClassObjectPointers {
                     $crate::ClassObjectPointers {
-                        function_pointers: vec![
+                        function_pointers: Box::new([
                             $(
                                 $crate::create_module!(@md $si $id $lbls $md $($sgns),+)
                             ),*
-                        ]
+                        ])
                     }
                 }
             }
@@ -8,7 +8,7 @@ use std::{
 
 pub use convert::*;
 
-use crate::{Class, SlotId, VM};
+use crate::{Class, ForeignSendError, MethodPointer, SlotId, SlotType, VM};
 
 /// Produce O given context Self::Context?
 pub trait Slottable<O> {
@@ -18,6 +18,14 @@ pub trait Slottable<O> {
         Self: Sized;
     fn get(ctx: &mut Self::Context, vm: &VM, slot: SlotId, scratch_start: SlotId) -> Option<O>;
 
+    /// The only kind of slot `O` can be read from, if there is just one
+    fn slot_type() -> Option<SlotType>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
     fn get_unknown_context(
         ctx: &mut dyn Any, vm: &VM, slot: SlotId, scratch_start: SlotId,
     ) -> Option<Option<O>>
@@ -45,6 +53,10 @@ where
     {
         T::try_from_vm(vm, slot, scratch_start)
     }
+
+    fn slot_type() -> Option<SlotType> {
+        T::SLOT_TYPE
+    }
 }
 
 impl<T> Slottable<Option<T>> for Option<T>
@@ -165,6 +177,30 @@ impl InputSlot {
     }
 }
 
+/// Check that `slot` holds the kind of value `O` is read from, for `#[wren_impl(validate_inputs)]`
+pub fn check_slot_type<O>(vm: &VM, slot: SlotId) -> Result<(), String>
+where
+    O: Slottable<O, Context = ()>,
+{
+    match O::slot_type() {
+        Some(expected) => check_slot_is(vm, slot, expected),
+        None => Ok(()),
+    }
+}
+
+/// Check that `slot` holds an `expected` value, for `#[wren_impl(validate_inputs)]`
+pub fn check_slot_is(vm: &VM, slot: SlotId, expected: SlotType) -> Result<(), String> {
+    let got = vm.get_slot_type(slot);
+    if got == expected {
+        Ok(())
+    } else {
+        Err(format!(
+            "expected {:?} for slot {} but got {:?}",
+            expected, slot, got
+        ))
+    }
+}
+
 pub fn get_slot_value<O>(vm: &VM, slot: &InputSlot, scratch_offset: usize) -> Option<O>
 where
     O: Slottable<O, Context = ()>,
@@ -172,6 +208,9 @@ where
     O::get(&mut (), vm, slot.slot, scratch_offset + slot.scratch_start)
 }
 
+/// Read the foreign object of type `T` in `slot`, using `ctx` as its class if it is one.
+///
+/// Otherwise, the class is looked up by `T`'s type, so objects from classes in other modules work too.
 pub fn get_slot_object<T, O>(
     vm: &VM, slot: &InputSlot, scratch_offset: usize, ctx: &mut dyn Any,
 ) -> Option<O>
@@ -180,9 +219,10 @@ where
     T::Class: 'static,
     T: 'static,
 {
-    match T::get_unknown_context(ctx, vm, slot.slot, scratch_offset + slot.scratch_start) {
+    let scratch_start = scratch_offset + slot.scratch_start;
+    match T::get_unknown_context(ctx, vm, slot.slot, scratch_start) {
         None => vm.use_class_mut::<T, _, _>(|vm, cls| {
-            cls.and_then(|class| T::get(class, vm, slot.slot, slot.scratch_start))
+            cls.and_then(|class| T::get(class, vm, slot.slot, scratch_start))
         }),
         Some(obj) => obj,
     }
@@ -190,10 +230,70 @@ where
 
 pub trait V2Class {
     fn name() -> &'static str;
-    fn allocate() -> Self;
+    /// Called the first time the class is used in `vm`.
+    ///
+    /// That can be from inside Wren's foreign allocator, where growing the slots (with
+    /// `ensure_slots`, or anything that needs a scratch slot) corrupts the constructor call.
+    fn allocate(vm: &VM) -> Self;
+}
+
+/// Marks a type as meant to be a Wren foreign class (or one of the pieces of one).
+///
+/// Required by [`ForeignItem`] and [`ClassObject`](crate::ClassObject), so ordinary Rust types
+/// can't be registered as foreign classes by accident.
+/// `#[derive(WrenObject)]` and `create_module!` implement it for you.
+pub trait WrenForeignType {}
+
+/// What `#[derive(WrenObject)]` decides on its own, and `wren_impl` picks up
+pub trait DerivedItems {
+    /// The Wren class name given with `#[wren(rename = "...")]`, if any
+    const WREN_NAME: Option<&'static str> = None;
+
+    /// Methods registered alongside a `wren_impl` block's
+    ///
+    /// For enums, these are static getters returning the index of each unit variant.
+    fn derived_methods() -> Vec<MethodPointer> {
+        vec![]
+    }
+}
+
+/// The Rust parameter names of a foreign class's methods, for [`class_stub`].
+///
+/// `wren_impl` implements this when ruwren's `stubs` feature is enabled.
+#[cfg(feature = "stubs")]
+pub trait WrenStub {
+    /// The parameters of the constructor, declared as `construct new(...)`
+    fn constructor_params() -> &'static [&'static str];
+
+    /// The parameters of the method registered with `signature`, if it was declared in Rust
+    fn method_params(
+        is_static: bool, signature: &crate::FunctionSignature,
+    ) -> Option<&'static [&'static str]>;
 }
 
-pub trait ForeignItem {
+/// Declare the foreign class `name` in Wren, with every method `T` registers
+#[cfg(feature = "stubs")]
+pub fn class_stub<T: crate::ClassObject + WrenStub>(name: &str) -> String {
+    let mut stub = format!(
+        "foreign class {} {{\n    construct new({}) {{}}\n",
+        name,
+        T::constructor_params().join(", ")
+    );
+    let pointers = T::generate_pointers();
+    if !pointers.function_pointers.is_empty() {
+        stub.push('\n');
+    }
+    for method in pointers.function_pointers.iter() {
+        let params = T::method_params(method.is_static, &method.signature).unwrap_or(&[]);
+        stub.push_str("    ");
+        stub.push_str(&method.signature.wren_stub(method.is_static, params));
+        stub.push('\n');
+    }
+    stub.push_str("}\n");
+    stub
+}
+
+pub trait ForeignItem: WrenForeignType {
     type Class: V2Class;
     type Source: for<'a> From<(&'a Self::Class, &'a Self)>;
 
@@ -207,7 +307,7 @@ pub trait ForeignItem {
     {
         vm.use_class_mut::<Self, _, _>(|vm, cls| cls.map(|class| Self::construct(class, vm)))
             .unwrap_or_else(|| {
-                let mut class = Self::Class::allocate();
+                let mut class = Self::Class::allocate(vm);
                 let inst = Self::construct(&mut class, vm);
                 vm.classes_v2.borrow_mut().insert(
                     TypeId::of::<Self>(),
@@ -229,3 +329,125 @@ where
         unreachable!("This is only implemented for compatibility, and should never be called for v2 foreigns")
     }
 }
+
+/// A foreign class listed in [`wren_module!`](crate::wren_module), which knows the Wren class
+/// to create for it.
+///
+/// Used by constructors marked with `#[wren_impl(constructor, error_type = "...")]`.
+pub trait SendForeign {
+    /// Creates a new instance of the Wren class in `slot`, holding `self`.
+    ///
+    /// The class is loaded into `scratch` first, which can be `slot` itself, so this works
+    /// without growing the slots, even from inside Wren's foreign allocator.
+    fn send_foreign(self, vm: &VM, slot: SlotId, scratch: SlotId) -> Result<(), ForeignSendError>;
+}
+
+/// A standalone Rust function (or closure) that Wren can call as a static method,
+/// see [`VM::register_wren_fn`](crate::VM::register_wren_fn)
+///
+/// Implemented for anything callable with up to 8 arguments that can be read from slots,
+/// and a return value that can be sent back to Wren.
+///
+/// Each registered method takes one of a fixed set of foreign methods, so a VM can only
+/// have [`MAX_WREN_FNS`] of them.
+pub trait IntoWrenFn<Args, Ret>: 'static {
+    /// The number of arguments Wren passes in
+    const ARITY: usize;
+
+    /// Read the arguments from slots `1..=ARITY`, call the function, and put the result in slot 0
+    fn call_from_vm(&self, vm: &VM) -> Result<(), String>;
+}
+
+macro_rules! into_wren_fn {
+    ($arity:expr; $($arg:ident $slot:expr),*) => {
+        impl<F, Ret, $($arg),*> IntoWrenFn<($($arg,)*), Ret> for F
+        where
+            F: Fn($($arg),*) -> Ret + 'static,
+            Ret: WrenTo,
+            $($arg: Slottable<$arg, Context = ()>,)*
+        {
+            const ARITY: usize = $arity;
+
+            #[allow(non_snake_case, unused_assignments, unused_mut, unused_variables)]
+            fn call_from_vm(&self, vm: &VM) -> Result<(), String> {
+                let mut prev: Option<InputSlot> = None;
+                $(
+                    let input = match &prev {
+                        None => InputSlot::new::<$arg>($slot, $arity),
+                        Some(prev) => InputSlot::next::<$arg>($slot, prev),
+                    };
+                    let Some($arg) = get_slot_value::<$arg>(vm, &input, $arity) else {
+                        return Err(format!(
+                            "failed to get value of type {} for slot {}",
+                            std::any::type_name::<$arg>(),
+                            $slot
+                        ));
+                    };
+                    prev = Some(input);
+                )*
+                WrenTo::to_vm(self($($arg),*), vm, 0, 1);
+                Ok(())
+            }
+        }
+    };
+}
+
+into_wren_fn!(0;);
+into_wren_fn!(1; A0 1);
+into_wren_fn!(2; A0 1, A1 2);
+into_wren_fn!(3; A0 1, A1 2, A2 3);
+into_wren_fn!(4; A0 1, A1 2, A2 3, A3 4);
+into_wren_fn!(5; A0 1, A1 2, A2 3, A3 4, A4 5);
+into_wren_fn!(6; A0 1, A1 2, A2 3, A3 4, A4 5, A5 6);
+into_wren_fn!(7; A0 1, A1 2, A2 3, A3 4, A4 5, A5 6, A6 7);
+into_wren_fn!(8; A0 1, A1 2, A2 3, A3 4, A4 5, A5 6, A6 7, A7 8);
+
+/// The foreign method Wren actually calls for the function registered `INDEX`th with
+/// [`VM::register_wren_fn`](crate::VM::register_wren_fn).
+///
+/// Wren doesn't tell a foreign method which method it was bound to, so each registration
+/// gets its own.
+unsafe extern "C" fn call_wren_fn<const INDEX: usize>(vm: *mut crate::wren_sys::WrenVM) {
+    use std::panic::AssertUnwindSafe;
+
+    let conf =
+        std::ptr::read_unaligned(crate::wren_sys::wrenGetUserData(vm) as *mut crate::UserData);
+    let ovm = vm;
+    let vm = std::rc::Weak::upgrade(&conf.vm)
+        .unwrap_or_else(|| panic!("Failed to access VM at {:p}", &conf.vm));
+    {
+        let vm_borrow = vm.borrow();
+        let func = vm_borrow
+            .wren_fn(INDEX)
+            .unwrap_or_else(|| panic!("no function was registered at {}", INDEX));
+        let res = crate::handle_panic(AssertUnwindSafe(|| func(&vm_borrow)))
+            .unwrap_or_else(|err| Err(crate::panic_message(err)));
+        if let Err(err) = res {
+            vm_borrow.set_slot_string(0, err);
+            vm_borrow.abort_fiber(0);
+        }
+    }
+    std::ptr::write_unaligned(
+        crate::wren_sys::wrenGetUserData(ovm) as *mut crate::UserData,
+        conf,
+    );
+}
+
+macro_rules! wren_fn_calls {
+    ($($index:literal)*) => {
+        [$(call_wren_fn::<$index>),*]
+    };
+}
+
+/// How many functions can be registered with [`VM::register_wren_fn`](crate::VM::register_wren_fn)
+///
+/// Wren binds foreign methods to plain function pointers, so each registered method uses one
+/// of these many pre-built ones, which find their closure by index. Registering a method again
+/// reuses its slot; registering more than this many methods is refused.
+pub const MAX_WREN_FNS: usize = 64;
+
+/// The foreign method for each registration index
+pub(crate) const WREN_FN_CALLS: [unsafe extern "C" fn(*mut crate::wren_sys::WrenVM); MAX_WREN_FNS] = wren_fn_calls!(
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+    32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47 48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63
+);
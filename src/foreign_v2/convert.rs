@@ -1,9 +1,11 @@
-use std::{any::type_name, collections::HashMap, string::FromUtf8Error};
+use std::{any::type_name, collections::HashMap, rc::Rc, string::FromUtf8Error, sync::Arc};
 
 use crate::{SlotId, SlotType, VM};
 
 pub trait WrenAtom {
     const SCRATCH_SPACE: usize = 0;
+    /// The only kind of slot this can be read from, if there is just one
+    const SLOT_TYPE: Option<SlotType> = None;
     fn to_vm(self, vm: &VM, slot: SlotId, scratch_start: SlotId);
     fn from_vm(vm: &VM, slot: SlotId, scratch_start: SlotId) -> Option<Self>
     where
@@ -49,12 +51,19 @@ pub trait WrenFrom: Sized {
     fn from_vm(vm: &VM, slot: SlotId, scratch_start: SlotId) -> Self;
 }
 
+/// Read a value out of a slot, or `None` if the slot holds something else.
+///
+/// This is how `wren_impl` methods get their arguments (through [`Slottable`](super::Slottable)),
+/// so implementing it for your own type lets methods take that type as a parameter.
+/// Simple values that also go back into slots can implement [`WrenAtom`] instead.
 pub trait WrenTryFrom: Sized {
     /// VM reserves (1 + SCRATCH_SPACE) when converting
     ///
     /// For example, if ScratchSpace == 1, then conversion functions
     /// can use `slot` and `scratch_start` in its implementation
     const SCRATCH_SPACE: usize = 0;
+    /// The only kind of slot this can be read from, if there is just one
+    const SLOT_TYPE: Option<SlotType> = None;
     /// Try to return a valid instance of this type from slot `slot`
     fn try_from_vm(vm: &VM, slot: SlotId, scratch_start: SlotId) -> Option<Self>;
 }
@@ -68,6 +77,7 @@ impl<T: WrenAtom> WrenTo for T {
 
 impl<T: WrenAtom> WrenTryFrom for T {
     const SCRATCH_SPACE: usize = <T as WrenAtom>::SCRATCH_SPACE;
+    const SLOT_TYPE: Option<SlotType> = <T as WrenAtom>::SLOT_TYPE;
     fn try_from_vm(vm: &VM, slot: SlotId, scratch_start: SlotId) -> Option<Self>
     where
         Self: Sized,
@@ -89,9 +99,11 @@ impl<T: WrenTryFrom> WrenFrom for T {
 }
 
 macro_rules! wren_convert {
-    (numeric $($ty:ty),+) => {
+    (float $($ty:ty),+) => {
             $(
                 impl WrenAtom for $ty {
+                    const SLOT_TYPE: Option<SlotType> = Some(SlotType::Num);
+
                     fn to_vm(self, vm: &VM, slot: SlotId, _scratch_start: SlotId) {
                         vm.set_slot_double(slot, self as f64)
                     }
@@ -107,6 +119,33 @@ macro_rules! wren_convert {
             )+
 
     };
+    // Fractions are truncated (as `as` does), but numbers that don't fit (including NaN) aren't
+    // extracted, rather than saturating
+    (integer $($ty:ty),+) => {
+            $(
+                impl WrenAtom for $ty {
+                    const SLOT_TYPE: Option<SlotType> = Some(SlotType::Num);
+
+                    fn to_vm(self, vm: &VM, slot: SlotId, _scratch_start: SlotId) {
+                        vm.set_slot_double(slot, self as f64)
+                    }
+
+                    fn from_vm(vm: &VM, slot: SlotId, _scratch_start: SlotId) -> Option<Self> {
+                        if vm.get_slot_type(slot) == SlotType::Num {
+                            // MAX + 1 is 2^N, exactly: either MAX converts exactly, or (for
+                            // 64 bit types) it rounds up to 2^N and adding 1 doesn't change it
+                            vm.get_slot_double(slot)
+                                .map(f64::trunc)
+                                .filter(|i| (<$ty>::MIN as f64..<$ty>::MAX as f64 + 1.0).contains(i))
+                                .map(|i| i as $ty)
+                        } else {
+                            None
+                        }
+                    }
+                }
+            )+
+
+    };
 }
 
 impl WrenAtom for () {
@@ -123,6 +162,8 @@ impl WrenAtom for () {
 }
 
 impl WrenAtom for bool {
+    const SLOT_TYPE: Option<SlotType> = Some(SlotType::Bool);
+
     fn to_vm(self, vm: &VM, slot: SlotId, _scratch_start: SlotId) {
         vm.set_slot_bool(slot, self)
     }
@@ -147,7 +188,14 @@ impl WrenTo for String {
     }
 }
 
-wren_convert!(numeric i8,i16,i32,i64,u8,u16,u32,u64,f32,f64);
+impl WrenTo for &str {
+    fn to_vm(self, vm: &VM, slot: SlotId, _scratch_start: SlotId) {
+        vm.set_slot_string(slot, self)
+    }
+}
+
+wren_convert!(integer i8,i16,i32,i64,isize,u8,u16,u32,u64,usize);
+wren_convert!(float f32,f64);
 
 #[derive(Debug)]
 pub struct WrenString(Vec<u8>);
@@ -167,6 +215,8 @@ impl WrenString {
 }
 
 impl WrenAtom for WrenString {
+    const SLOT_TYPE: Option<SlotType> = Some(SlotType::String);
+
     fn to_vm(self, vm: &VM, slot: SlotId, _scratch_start: SlotId) {
         vm.set_slot_bytes(slot, &self.0)
     }
@@ -183,7 +233,7 @@ impl WrenAtom for WrenString {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum WrenValue {
     Null,
     Number(f64),
@@ -228,6 +278,46 @@ where
     }
 }
 
+/// Only boxed atoms are covered: `Box` is `#[fundamental]`, so a `WrenTo` impl for any `Box<T>`
+/// would overlap with the blanket one for atoms (other crates may make `Box<TheirType>` an atom)
+impl<T> WrenAtom for Box<T>
+where
+    T: WrenAtom,
+{
+    const SCRATCH_SPACE: usize = <T as WrenAtom>::SCRATCH_SPACE;
+    const SLOT_TYPE: Option<SlotType> = <T as WrenAtom>::SLOT_TYPE;
+
+    fn to_vm(self, vm: &VM, slot: SlotId, scratch_start: SlotId) {
+        <T as WrenAtom>::to_vm(*self, vm, slot, scratch_start)
+    }
+
+    fn from_vm(vm: &VM, slot: SlotId, scratch_start: SlotId) -> Option<Self> {
+        <T as WrenAtom>::from_vm(vm, slot, scratch_start).map(Box::new)
+    }
+}
+
+/// Sends the inner value, cloning it only if the `Rc` is shared
+impl<T> WrenTo for Rc<T>
+where
+    T: WrenTo + Clone,
+{
+    const SCRATCH_SPACE: usize = T::SCRATCH_SPACE;
+    fn to_vm(self, vm: &VM, slot: SlotId, scratch_start: SlotId) {
+        T::to_vm(Rc::unwrap_or_clone(self), vm, slot, scratch_start)
+    }
+}
+
+/// Sends the inner value, cloning it only if the `Arc` is shared
+impl<T> WrenTo for Arc<T>
+where
+    T: WrenTo + Clone,
+{
+    const SCRATCH_SPACE: usize = T::SCRATCH_SPACE;
+    fn to_vm(self, vm: &VM, slot: SlotId, scratch_start: SlotId) {
+        T::to_vm(Arc::unwrap_or_clone(self), vm, slot, scratch_start)
+    }
+}
+
 impl<const N: usize, T> WrenTo for [T; N]
 where
     T: WrenTo,
@@ -248,6 +338,7 @@ where
     T: WrenTryFrom,
 {
     const SCRATCH_SPACE: usize = 1 + T::SCRATCH_SPACE;
+    const SLOT_TYPE: Option<SlotType> = Some(SlotType::List);
 
     fn try_from_vm(vm: &VM, slot: SlotId, scratch_start: SlotId) -> Option<Self>
     where
@@ -295,6 +386,7 @@ where
     T: WrenTryFrom,
 {
     const SCRATCH_SPACE: usize = 1 + T::SCRATCH_SPACE;
+    const SLOT_TYPE: Option<SlotType> = Some(SlotType::List);
 
     fn try_from_vm(vm: &VM, slot: SlotId, scratch_start: SlotId) -> Option<Self>
     where
@@ -314,6 +406,38 @@ where
     }
 }
 
+macro_rules! wren_to_tuple {
+    ($(($($name:ident $idx:tt),+))+) => {
+        $(
+            /// Sent as a Wren list of the elements, in order
+            impl<$($name),+> WrenTo for ($($name,)+)
+            where
+                $($name: WrenTo,)+
+            {
+                const SCRATCH_SPACE: usize = 1 $(+ $name::SCRATCH_SPACE)+;
+
+                fn to_vm(self, vm: &VM, slot: SlotId, scratch_start: SlotId) {
+                    vm.set_slot_new_list(slot);
+                    $(
+                        self.$idx.to_vm(vm, scratch_start, scratch_start + 1);
+                        vm.insert_in_list(slot, $idx, scratch_start);
+                    )+
+                }
+            }
+        )+
+    };
+}
+
+wren_to_tuple! {
+    (A 0, B 1)
+    (A 0, B 1, C 2)
+    (A 0, B 1, C 2, D 3)
+    (A 0, B 1, C 2, D 3, E 4)
+    (A 0, B 1, C 2, D 3, E 4, F 5)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7)
+}
+
 impl<K, V> WrenTo for HashMap<K, V>
 where
     K: WrenAtom,
@@ -1,12 +1,14 @@
 //! We expose the Wren API in a Rust-y way
 pub extern crate wren_sys;
 
-use foreign_v2::ForeignItem;
+use foreign_v2::{ForeignItem, WrenAtom, WrenTryFrom, WrenValue};
 use std::any::{Any, TypeId};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::{Rc, Weak};
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::Duration;
 use wren_sys::{wrenGetUserData, WrenConfiguration, WrenHandle, WrenVM};
 
 mod module_loader;
@@ -31,6 +33,32 @@ pub enum WrenError {
     StackTrace(String, i32, String),
 }
 
+impl std::fmt::Display for WrenError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WrenError::Compile(module, line, msg) if module.is_empty() || *line < 1 => {
+                write!(fmt, "wren compile error: {}", msg)
+            }
+            WrenError::Compile(module, line, msg) => {
+                write!(fmt, "wren compile error at {}:{}: {}", module, line, msg)
+            }
+            WrenError::Runtime(msg) => write!(fmt, "wren runtime error: {}", msg),
+            WrenError::StackTrace(module, line, function) if function.is_empty() => {
+                write!(
+                    fmt,
+                    "wren stack frame at {}:{}: <constructor>",
+                    module, line
+                )
+            }
+            WrenError::StackTrace(module, line, function) => {
+                write!(fmt, "wren stack frame at {}:{}: {}", module, line, function)
+            }
+        }
+    }
+}
+
+impl std::error::Error for WrenError {}
+
 #[derive(Debug, Clone)]
 /// Possible errors for a Wren script
 pub enum VMError {
@@ -43,6 +71,10 @@ pub enum VMError {
         error: String,
         frames: Vec<VMStackFrameError>,
     },
+    TypeMismatch {
+        expected: String,
+        got: SlotType,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -71,6 +103,17 @@ where
     }
 }
 
+/// Turn the payload of a caught panic into a message that can be reported to Wren
+pub fn panic_message(err: Box<dyn Any + Send>) -> String {
+    if let Some(strg) = err.downcast_ref::<String>() {
+        strg.clone()
+    } else if let Some(strg) = err.downcast_ref::<&str>() {
+        strg.to_string()
+    } else {
+        "Non-string panic message".into()
+    }
+}
+
 impl std::fmt::Display for VMError {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -94,6 +137,9 @@ impl std::fmt::Display for VMError {
                 }
                 Ok(())
             }
+            VMError::TypeMismatch { expected, got } => {
+                write!(fmt, "Type Mismatch: expected {}, got {:?}", expected, got)
+            }
         }
     }
 }
@@ -144,6 +190,49 @@ impl ModuleLibrary {
         }
     }
 
+    /// Whether a module called `name` has been added, so [`module`](Self::module) would merge into it
+    pub fn module_exists<N: AsRef<str>>(&self, name: N) -> bool {
+        self.modules.contains_key(name.as_ref())
+    }
+
+    /// Adds every module from `other` into this library.
+    ///
+    /// A conflict is a class with the same name in the same module of both libraries,
+    /// and `policy` decides what happens to it. With [`MergePolicy::ErrorOnConflict`],
+    /// nothing is merged if there are any conflicts.
+    pub fn merge(
+        &mut self, other: ModuleLibrary, policy: MergePolicy,
+    ) -> Result<(), Vec<ConflictError>> {
+        if policy == MergePolicy::ErrorOnConflict {
+            let mut conflicts = vec![];
+            for (module, md) in &other.modules {
+                for class in md.classes.keys() {
+                    if self.get_foreign_class(module, class).is_some() {
+                        conflicts.push(ConflictError {
+                            module: module.clone(),
+                            class: class.clone(),
+                        });
+                    }
+                }
+            }
+            if !conflicts.is_empty() {
+                conflicts.sort_by(|a, b| (&a.module, &a.class).cmp(&(&b.module, &b.class)));
+                return Err(conflicts);
+            }
+        }
+
+        for (module_name, modl) in other.modules {
+            let module = self.modules.entry(module_name).or_default();
+            for (class, rc) in modl.classes {
+                if policy == MergePolicy::SkipOnConflict && module.classes.contains_key(&class) {
+                    continue;
+                }
+                module.classes.insert(class, rc);
+            }
+        }
+        Ok(())
+    }
+
     /// Attempts to find a [`RuntimeClass`] given a `module` name and a `class` name
     fn get_foreign_class<M: AsRef<str>, C: AsRef<str>>(
         &self, module: M, class: C,
@@ -154,6 +243,36 @@ impl ModuleLibrary {
     }
 }
 
+/// What [`ModuleLibrary::merge`] does when both libraries have the same class
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Merge nothing, and report every conflict
+    ErrorOnConflict,
+    /// Replace the existing class with the incoming one
+    OverwriteOnConflict,
+    /// Keep the existing class
+    SkipOnConflict,
+}
+
+/// A class that exists in both libraries given to [`ModuleLibrary::merge`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictError {
+    pub module: String,
+    pub class: String,
+}
+
+impl std::fmt::Display for ConflictError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "class {} is already defined in module {}",
+            self.class, self.module
+        )
+    }
+}
+
+impl std::error::Error for ConflictError {}
+
 #[derive(Debug, Clone)]
 /// Represetnation of classes at runtime
 struct RuntimeClass {
@@ -174,7 +293,28 @@ pub struct Module {
 #[derive(Debug, Clone)]
 /// List of [`MethodPointer`]s that make up the methods of a ['RuntimeClass`]
 pub struct ClassObjectPointers {
-    pub function_pointers: Vec<MethodPointer>,
+    pub function_pointers: Box<[MethodPointer]>,
+}
+
+impl ClassObjectPointers {
+    /// Sort the methods by staticness, then Wren signature, so [`find`](Self::find) can use a binary search
+    pub fn sorted(mut self) -> Self {
+        self.function_pointers
+            .sort_by_cached_key(|mp| (mp.is_static, mp.signature.as_wren_string()));
+        self
+    }
+
+    /// Find the method Wren binds to `signature` (like `"add(_,_)"`), if the pointers are [`sorted`](Self::sorted)
+    ///
+    /// When a signature is declared more than once, the first declaration wins.
+    pub fn find(&self, is_static: bool, signature: &str) -> Option<&MethodPointer> {
+        let idx = self.function_pointers.partition_point(|mp| {
+            (mp.is_static, mp.signature.as_wren_string().as_str()) < (is_static, signature)
+        });
+        self.function_pointers
+            .get(idx)
+            .filter(|mp| mp.is_static == is_static && mp.signature.as_wren_string() == signature)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -194,7 +334,7 @@ impl Module {
 
     /// Add class `C` to this module with a `name`
     pub fn class<C: 'static + ClassObject, S: Into<String>>(&mut self, name: S) -> &mut Self {
-        let cp = C::generate_pointers();
+        let cp = C::generate_pointers().sorted();
         let init = C::initialize_pointer();
         let deinit = C::finalize_pointer();
         self.classes.insert(
@@ -208,8 +348,85 @@ impl Module {
         );
         self
     }
+
+    /// Whether a class called `name` has been added, so [`class`](Self::class) would replace it
+    pub fn class_exists<N: AsRef<str>>(&self, name: N) -> bool {
+        self.classes.contains_key(name.as_ref())
+    }
+}
+
+/// Builds up a [`Module`], so that several sources can contribute classes before it's published
+#[derive(Debug, Clone, Default)]
+pub struct ModuleBuilder {
+    module: Module,
+    duplicates: Vec<String>,
+}
+
+impl ModuleBuilder {
+    /// Create a new, empty builder
+    pub fn new() -> ModuleBuilder {
+        ModuleBuilder::default()
+    }
+
+    /// Add class `C` with a `name`
+    pub fn add_class<C: 'static + ClassObject, S: Into<String>>(&mut self, name: S) -> &mut Self {
+        let name = name.into();
+        if self.module.class_exists(&name) {
+            self.duplicates.push(name.clone());
+        }
+        self.module.class::<C, _>(name);
+        self
+    }
+
+    /// Add all the classes of `other` to this builder
+    pub fn merge(&mut self, other: ModuleBuilder) -> &mut Self {
+        self.duplicates.extend(other.duplicates);
+        for (name, class) in other.module.classes {
+            if self.module.classes.contains_key(&name) {
+                self.duplicates.push(name.clone());
+            }
+            self.module.classes.insert(name, class);
+        }
+        self
+    }
+
+    /// Checks that no class name was added more than once
+    pub fn validate(&self) -> Result<(), ModuleBuildError> {
+        match self.duplicates.first() {
+            Some(name) => Err(ModuleBuildError::DuplicateClass(name.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Create the [`Module`]. When a class name was added more than once, the last class wins.
+    pub fn build(self) -> Module {
+        self.module
+    }
+}
+
+impl From<ModuleBuilder> for Module {
+    fn from(builder: ModuleBuilder) -> Module {
+        builder.build()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Errors found when validating a [`ModuleBuilder`]
+pub enum ModuleBuildError {
+    /// More than one class was added with this name
+    DuplicateClass(String),
+}
+
+impl std::fmt::Display for ModuleBuildError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ModuleBuildError::DuplicateClass(name) => write!(fmt, "duplicate class {}", name),
+        }
+    }
 }
 
+impl std::error::Error for ModuleBuildError {}
+
 /// Initialize function for Wren classes
 pub trait Class {
     fn initialize(_: &VM) -> Self
@@ -218,7 +435,7 @@ pub trait Class {
 }
 
 /// Indicates a "real" Wren class, and must be implemented to be added to a [`Module`]
-pub trait ClassObject: Class {
+pub trait ClassObject: Class + foreign_v2::WrenForeignType {
     fn initialize_pointer() -> extern "C" fn(*mut WrenVM)
     where
         Self: Sized;
@@ -230,11 +447,121 @@ pub trait ClassObject: Class {
         Self: Sized;
 }
 
-#[derive(Debug, Copy, Clone)]
 /// Indicates a "foreign object" to Wren
+///
+/// Lives in memory Wren owns, so it's never dropped there: the object is only freed by
+/// [`finalize`](ForeignObject::finalize), which marks it as no longer live. One that's
+/// dropped without being handed to Wren finalizes itself.
+///
+/// Objects no bigger than two pointers are stored inline, anything larger is boxed.
+/// Inline objects move with the `ForeignObject`, so they must be reached in place,
+/// through [`object_ptr`](ForeignObject::object_ptr), and not through a copy of it.
 pub struct ForeignObject<T> {
-    pub object: *mut T,
+    // The object itself if it's INLINE, otherwise a Box<T> turned into a raw pointer
+    storage: [mem::MaybeUninit<usize>; 2],
+    live: bool,
     pub type_id: any::TypeId,
+    // Shared with the VM, so it's still there if the object outlives it
+    live_count: Option<Rc<Cell<isize>>>,
+    object: marker::PhantomData<T>,
+}
+
+impl<T: 'static> ForeignObject<T> {
+    /// Stores `object` (moving it onto the heap if it's too big to be inline),
+    /// counting it as live in `vm` until it's finalized
+    pub fn new(vm: &VM, object: T) -> Self {
+        let type_id = any::TypeId::of::<T>();
+        let mut counts = vm.foreign_counts.borrow_mut();
+        let (_, count) = counts
+            .entry(type_id)
+            .or_insert_with(|| (any::type_name::<T>(), Rc::default()));
+        count.set(count.get() + 1);
+        let mut storage = [mem::MaybeUninit::uninit(); 2];
+        unsafe {
+            if Self::INLINE {
+                storage.as_mut_ptr().cast::<T>().write(object);
+            } else {
+                storage
+                    .as_mut_ptr()
+                    .cast::<*mut T>()
+                    .write(Box::into_raw(Box::new(object)));
+            }
+        }
+        ForeignObject {
+            storage,
+            live: true,
+            type_id,
+            live_count: Some(Rc::clone(count)),
+            object: marker::PhantomData,
+        }
+    }
+}
+
+impl<T> ForeignObject<T> {
+    // Wren's foreign data is at least pointer-aligned (see runtime::wren_realloc),
+    // and so is storage inside it
+    const INLINE: bool = mem::size_of::<T>() <= mem::size_of::<[usize; 2]>()
+        && mem::align_of::<T>() <= mem::align_of::<usize>();
+
+    /// Where the object is, or `None` if it's been finalized.
+    ///
+    /// # Safety
+    /// `this` must point to a `ForeignObject<T>` in pointer-aligned memory (like Wren's foreign data).
+    pub unsafe fn object_ptr(this: *mut Self) -> Option<*mut T> {
+        if !std::ptr::addr_of!((*this).live).read_unaligned() {
+            return None;
+        }
+        let storage = std::ptr::addr_of_mut!((*this).storage);
+        if Self::INLINE {
+            Some(storage.cast::<T>())
+        } else {
+            Some(storage.cast::<*mut T>().read_unaligned())
+        }
+    }
+
+    /// The object, or `None` if it's been finalized
+    pub fn object_mut(&mut self) -> Option<&mut T> {
+        unsafe { Self::object_ptr(self).map(|object| &mut *object) }
+    }
+
+    /// Drops the object (if it hasn't been already), and stops counting it as live.
+    ///
+    /// # Safety
+    /// Must only be called from the finalizer Wren runs for this object.
+    pub unsafe fn finalize(&mut self) {
+        if let Some(object) = Self::object_ptr(self) {
+            self.live = false;
+            if Self::INLINE {
+                std::ptr::drop_in_place(object);
+            } else {
+                drop(Box::from_raw(object));
+            }
+            if let Some(count) = self.live_count.take() {
+                count.set(count.get() - 1);
+            }
+        }
+    }
+}
+
+impl<T> Drop for ForeignObject<T> {
+    fn drop(&mut self) {
+        // Wren never drops its copy, so this one was never handed over to be finalized
+        unsafe { self.finalize() }
+    }
+}
+
+/// Shows the object itself (so foreign objects can be `dbg!`ed and appear in assertion
+/// messages), or `<finalized>` once Wren has freed it
+impl<T: std::fmt::Debug> std::fmt::Debug for ForeignObject<T> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut fmt = fmt.debug_struct("ForeignObject");
+        // object_ptr only reads through the pointer, so it's fine to hand it a shared one
+        match unsafe { Self::object_ptr(self as *const Self as *mut Self) } {
+            Some(object) => fmt.field("object", unsafe { &*object }),
+            None => fmt.field("object", &format_args!("<finalized>")),
+        };
+        fmt.finish()
+    }
 }
 
 pub fn type_name_of<T>(_: &T) -> &'static str {
@@ -284,13 +611,58 @@ impl Printer for PrintlnPrinter {
     }
 }
 
+type WriteHandler = Rc<dyn Fn(&str)>;
+
+/// Write handlers installed with [`VM::subscribe_write`], newest last
+#[derive(Default)]
+struct WriteSubscribers {
+    next_id: usize,
+    handlers: Vec<(usize, WriteHandler)>,
+}
+
+impl std::fmt::Debug for WriteSubscribers {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("WriteSubscribers")
+            .field("subscribed", &self.handlers.len())
+            .finish()
+    }
+}
+
+type WrenFnCall = Rc<dyn Fn(&VM) -> Result<(), String>>;
+
+/// Functions registered with [`VM::register_wren_fn`]
+#[derive(Default)]
+struct WrenFns {
+    // By registration index, which picks the foreign method Wren calls for them
+    calls: Vec<WrenFnCall>,
+    // The index of the function bound to each module, class and signature
+    indices: HashMap<(String, String, String), usize>,
+}
+
+impl std::fmt::Debug for WrenFns {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("WrenFns")
+            .field("registered", &self.calls.len())
+            .finish()
+    }
+}
+
 type ClassMap = RefCell<HashMap<TypeId, Rc<RefCell<Box<dyn Any>>>>>;
+// Each with a check that it still holds the guard's (live) object
+type GuardedSlots = RefCell<Vec<(SlotId, fn(&VM, SlotId) -> bool)>>;
+type ForeignCounts = RefCell<HashMap<TypeId, (&'static str, Rc<Cell<isize>>)>>;
 
 #[derive(Debug)]
 pub struct VM {
     pub vm: *mut WrenVM,
     classes_v2: ClassMap,
     error_recv: Receiver<WrenError>,
+    guarded_slots: GuardedSlots,
+    interpret_timeout: Option<Duration>,
+    // Boxed so every ForeignObject can point at its count. Dropped after wrenFreeVM finalizes them.
+    foreign_counts: ForeignCounts,
+    write_subscribers: Rc<RefCell<WriteSubscribers>>,
+    wren_fns: RefCell<WrenFns>,
 }
 
 /// A mostly internal class that is exposed so that some externally generated code can access it.
@@ -318,11 +690,16 @@ pub enum SlotType {
 pub type SlotId = usize;
 
 /// Represents Wren function signatures
-#[derive(Debug, Clone)]
+///
+/// Signatures order by name, then arity, so a getter `x`, a method `x()` and a setter `x=(_)`
+/// sort next to each other (and are still distinct keys).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum FunctionSignature {
     Function { name: String, arity: usize },
     Getter(String),
     Setter(String),
+    SubscriptGetter,
+    SubscriptSetter,
 }
 
 impl FunctionSignature {
@@ -341,6 +718,16 @@ impl FunctionSignature {
         FunctionSignature::Setter(name.into())
     }
 
+    /// The signature of a subscript getter, `[_]`
+    pub fn new_subscript_getter() -> FunctionSignature {
+        FunctionSignature::SubscriptGetter
+    }
+
+    /// The signature of a subscript setter, `[_]=(_)`
+    pub fn new_subscript_setter() -> FunctionSignature {
+        FunctionSignature::SubscriptSetter
+    }
+
     fn as_wren_string(&self) -> String {
         match self {
             FunctionSignature::Function { name, arity } => {
@@ -348,6 +735,18 @@ impl FunctionSignature {
             }
             FunctionSignature::Getter(name) => name.clone(),
             FunctionSignature::Setter(name) => format!("{}=(_)", name),
+            FunctionSignature::SubscriptGetter => "[_]".to_string(),
+            FunctionSignature::SubscriptSetter => "[_]=(_)".to_string(),
+        }
+    }
+
+    /// Get the name of the function, without any argument list or `=`
+    pub fn name(&self) -> &str {
+        match self {
+            FunctionSignature::Function { name, .. } => name,
+            FunctionSignature::Getter(name) => name,
+            FunctionSignature::Setter(name) => name,
+            FunctionSignature::SubscriptGetter | FunctionSignature::SubscriptSetter => "[]",
         }
     }
 
@@ -357,7 +756,57 @@ impl FunctionSignature {
             FunctionSignature::Function { arity, .. } => *arity,
             FunctionSignature::Getter(_) => 0,
             FunctionSignature::Setter(_) => 1,
+            FunctionSignature::SubscriptGetter => 1,
+            FunctionSignature::SubscriptSetter => 2,
+        }
+    }
+
+    /// The Wren declaration of a foreign method with this signature,
+    /// like `foreign static add(a, b)`
+    ///
+    /// Parameters without a name in `params` are called `arg0`, `arg1`, and so on.
+    #[cfg(feature = "stubs")]
+    pub fn wren_stub(&self, is_static: bool, params: &[&str]) -> String {
+        let params: Vec<_> = (0..self.arity())
+            .map(|i| match params.get(i) {
+                Some(param) => param.to_string(),
+                None => format!("arg{}", i),
+            })
+            .collect();
+        let decl = match self {
+            FunctionSignature::Function { name, .. } => format!("{}({})", name, params.join(", ")),
+            FunctionSignature::Getter(name) => name.clone(),
+            FunctionSignature::Setter(name) => format!("{}=({})", name, params[0]),
+            FunctionSignature::SubscriptGetter => format!("[{}]", params[0]),
+            FunctionSignature::SubscriptSetter => format!("[{}]=({})", params[0], params[1]),
+        };
+        if is_static {
+            format!("foreign static {}", decl)
+        } else {
+            format!("foreign {}", decl)
+        }
+    }
+}
+
+impl PartialOrd for FunctionSignature {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FunctionSignature {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // Break ties between kinds that share a name and arity (getter vs method, etc.)
+        fn kind(sig: &FunctionSignature) -> u8 {
+            match sig {
+                FunctionSignature::Function { .. } => 0,
+                FunctionSignature::Getter(_) => 1,
+                FunctionSignature::Setter(_) => 2,
+                FunctionSignature::SubscriptGetter => 3,
+                FunctionSignature::SubscriptSetter => 4,
+            }
         }
+        (self.name(), self.arity(), kind(self)).cmp(&(other.name(), other.arity(), kind(other)))
     }
 }
 
@@ -375,6 +824,14 @@ impl VMWrapper {
     /// Calls a given function from its handle
     pub fn call_handle(&self, handle: &FunctionHandle) -> Result<(), VMError> {
         let vm = self.0.borrow();
+        // The result goes in slot 0, and the other slots are discarded (and the call could
+        // borrow a guarded object mutably)
+        if let Some((slot, _)) = vm.guarded_slots.borrow().first() {
+            panic!(
+                "slot {} is locked by a SlotGuard, and a call would replace it",
+                slot
+            );
+        }
         match unsafe { wren_sys::wrenCall(vm.vm, handle.0.handle) } {
             wren_sys::WrenInterpretResult_WREN_RESULT_SUCCESS => Ok(()),
             wren_sys::WrenInterpretResult_WREN_RESULT_COMPILE_ERROR => {
@@ -404,6 +861,35 @@ impl VMWrapper {
         }
     }
 
+    /// Calls a function from a pre-built handle, with the receiver taken from `receiver_slot`
+    /// and `args` placed in the following slots
+    ///
+    /// Cache the handle from [`make_call_handle`](VMWrapper::make_call_handle) to skip
+    /// rebuilding it on every call in hot loops. Wren only leaves the result in slot 0
+    /// after a call, so the receiver has to be reloaded before each call.
+    ///
+    /// Fails with [`VMError::TypeMismatch`] if the result can't be represented as a [`WrenValue`]
+    pub fn call_function_by_handle(
+        &self, receiver_slot: SlotId, handle: &FunctionHandle, args: &[WrenValue],
+    ) -> Result<WrenValue, VMError> {
+        self.execute(|vm| {
+            vm.ensure_slots(receiver_slot.max(args.len()) + 1);
+            if receiver_slot != 0 {
+                vm.copy_slot(0, receiver_slot);
+            }
+            for (idx, arg) in args.iter().enumerate() {
+                WrenAtom::to_vm(arg.clone(), vm, idx + 1, args.len() + 1);
+            }
+        });
+        self.call_handle(handle)?;
+        self.execute(|vm| {
+            WrenValue::from_vm(vm, 0, 1).ok_or_else(|| VMError::TypeMismatch {
+                expected: "a null, number, string or bool".to_string(),
+                got: vm.get_slot_type(0),
+            })
+        })
+    }
+
     /// Interprets a given string as Wren code
     pub fn interpret<M: AsRef<str>, C: AsRef<str>>(
         &self, module: M, code: C,
@@ -411,7 +897,12 @@ impl VMWrapper {
         let module = ffi::CString::new(module.as_ref()).expect("module name conversion failed");
         let code = ffi::CString::new(code.as_ref()).expect("code conversion failed");
         let vm = self.0.borrow();
-        match unsafe { wren_sys::wrenInterpret(vm.vm, module.as_ptr(), code.as_ptr()) } {
+        let watchdog = vm
+            .interpret_timeout
+            .map(|timeout| Watchdog::start(vm.vm, timeout));
+        let result = unsafe { wren_sys::wrenInterpret(vm.vm, module.as_ptr(), code.as_ptr()) };
+        drop(watchdog);
+        match result {
             wren_sys::WrenInterpretResult_WREN_RESULT_SUCCESS => Ok(()),
             wren_sys::WrenInterpretResult_WREN_RESULT_COMPILE_ERROR => {
                 match vm.error_recv.try_recv() {
@@ -452,6 +943,86 @@ impl VMWrapper {
         }
     }
 
+    /// Imports `module` from the `main` module, loading it (and running its top-level code)
+    /// if it hasn't been imported yet. Nothing is added to `main`'s variables.
+    pub fn import_module<M: AsRef<str>>(&self, module: M) -> Result<(), VMError> {
+        let escaped = module
+            .as_ref()
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "\\%");
+        self.interpret("main", format!("import \"{}\"", escaped))
+    }
+
+    /// Evaluates the expression `expr` in the `main` module, and converts its value to a `T`
+    pub fn eval_expression_typed<T: WrenTryFrom, E: AsRef<str>>(
+        &self, expr: E,
+    ) -> Result<T, VMError> {
+        self.eval_in_module("main", expr.as_ref())
+    }
+
+    /// Interprets `source` in `module`, and converts the value of its last statement to a `T`.
+    ///
+    /// Wren doesn't hand back the value of a module's last expression, so the last top-level
+    /// statement (which may span several lines) is split off and evaluated on its own, after
+    /// the rest of the source has run. That statement has to be an expression.
+    pub fn interpret_and_capture<T: WrenTryFrom, M: AsRef<str>, S: AsRef<str>>(
+        &self, module: M, source: S,
+    ) -> Result<T, VMError> {
+        let (body, last) = split_last_statement(source.as_ref());
+        if !body.trim().is_empty() {
+            self.interpret(module.as_ref(), body)?;
+        }
+        // Keeps the expression on its own lines, so errors in it point at the right one
+        let last = "\n".repeat(body.matches('\n').count()) + last;
+        self.eval_in_module(module.as_ref(), &last)
+    }
+
+    fn eval_in_module<T: WrenTryFrom>(&self, module: &str, expr: &str) -> Result<T, VMError> {
+        // Compiled in the module (so it sees the module's variables) into a function of its own,
+        // which is called through a handle, so nothing is left behind in the module
+        let module = ffi::CString::new(module).expect("module name conversion failed");
+        let expr = ffi::CString::new(expr).expect("code conversion failed");
+        let function = {
+            let vm = self.0.borrow();
+            let handle =
+                unsafe { wren_sys::wrenCompileExpression(vm.vm, module.as_ptr(), expr.as_ptr()) };
+            if handle.is_null() {
+                return match vm.error_recv.try_recv() {
+                    Ok(WrenError::Compile(module, line, msg)) => Err(VMError::Compile {
+                        module,
+                        line,
+                        error: msg,
+                    }),
+                    _ => unreachable!(),
+                };
+            }
+            Handle {
+                handle,
+                wvm: vm.vm,
+                vm: marker::PhantomData,
+            }
+        };
+        self.execute(|vm| vm.ensure_slots(1));
+        self.set_slot_handle(0, &function);
+        let call = self.make_call_handle(FunctionSignature::new_function("call", 0));
+        let watchdog = self.execute(|vm| {
+            vm.interpret_timeout
+                .map(|timeout| Watchdog::start(vm.vm, timeout))
+        });
+        let called = self.call_handle(&call);
+        drop(watchdog);
+        called?;
+        self.execute(|vm| {
+            vm.ensure_slots(1 + T::SCRATCH_SPACE);
+            let got = vm.get_slot_type(0);
+            T::try_from_vm(vm, 0, 1).ok_or_else(|| VMError::TypeMismatch {
+                expected: any::type_name::<T>().to_string(),
+                got,
+            })
+        })
+    }
+
     /// Allows access to the internal VM wrapper object
     pub fn execute<T, F>(&self, f: F) -> T
     where
@@ -461,7 +1032,7 @@ impl VMWrapper {
     }
 
     /// Gets a handle to a value in a certain slot
-    pub fn get_slot_handle(&self, slot: SlotId) -> Rc<Handle> {
+    pub fn get_slot_handle(&self, slot: SlotId) -> Rc<Handle<'_>> {
         Rc::new(Handle {
             handle: unsafe { wren_sys::wrenGetSlotHandle(self.0.borrow().vm, slot as raw::c_int) },
             wvm: self.0.borrow().vm,
@@ -471,13 +1042,34 @@ impl VMWrapper {
 
     /// Sets the value in a certain slot to the value of a handle
     pub fn set_slot_handle(&self, slot: SlotId, handle: &Handle) {
+        self.0.borrow().assert_unguarded(slot);
         unsafe {
             wren_sys::wrenSetSlotHandle(self.0.borrow().vm, slot as raw::c_int, handle.handle)
         }
     }
 
+    /// Places the foreign object held by `handle` into `slot`, without allocating a new one.
+    ///
+    /// Wren's GC owns the object, `handle` only keeps it alive, so it must never be freed from Rust.
+    /// If `handle` doesn't hold a `T`, `slot` is set to `null`, and
+    /// [`ForeignSendError::ClassMismatch`] is returned.
+    pub fn set_slot_foreign_existing<T: 'static + ClassObject>(
+        &self, slot: SlotId, handle: &Handle,
+    ) -> Result<(), ForeignSendError> {
+        self.execute(|vm| vm.ensure_slots(slot + 1));
+        self.set_slot_handle(slot, handle);
+        self.execute(|vm| {
+            if vm.get_slot_foreign::<T>(slot).is_some() {
+                Ok(())
+            } else {
+                vm.set_slot_null(slot);
+                Err(ForeignSendError::ClassMismatch)
+            }
+        })
+    }
+
     /// Create a callable handle, that can be used with [`call_handle`](VMWrapper::call_handle)
-    pub fn make_call_handle(&self, signature: FunctionSignature) -> Rc<FunctionHandle> {
+    pub fn make_call_handle(&self, signature: FunctionSignature) -> Rc<FunctionHandle<'_>> {
         VM::make_call_handle(self.0.borrow().vm, signature)
     }
 
@@ -485,6 +1077,11 @@ impl VMWrapper {
     pub fn collect_garbage(&self) {
         unsafe { wren_sys::wrenCollectGarbage(self.0.borrow().vm) }
     }
+
+    /// Capture everything Wren writes until the subscription is dropped, see [`VM::subscribe_write`]
+    pub fn subscribe_write<F: Fn(&str) + 'static>(&self, handler: F) -> WriteSubscription {
+        self.0.borrow().subscribe_write(handler)
+    }
 }
 
 /// Allows for the customization of a Wren VM
@@ -498,6 +1095,9 @@ pub struct VMConfig {
 
     /// Enables @module syntax to mean `module` loaded relative to current module
     enable_relative_import: bool,
+
+    /// How long a single [`VMWrapper::interpret`] may run before it's interrupted
+    interpret_timeout_ms: Option<u64>,
 }
 
 impl Default for VMConfig {
@@ -516,6 +1116,7 @@ impl VMConfig {
             min_heap_size: 1024 * 1024,
             heap_growth_percent: 50,
             enable_relative_import: false,
+            interpret_timeout_ms: None,
         }
     }
 
@@ -559,6 +1160,15 @@ impl VMConfig {
         self
     }
 
+    /// Interrupt every [`VMWrapper::interpret`] that runs for longer than `ms` milliseconds.
+    ///
+    /// Each call starts a watchdog thread, so this isn't available on targets without threads.
+    /// An interrupted script fails with a runtime error, even if it tries to catch it.
+    pub fn interpret_timeout_ms(mut self, ms: u64) -> Self {
+        self.interpret_timeout_ms = Some(ms);
+        self
+    }
+
     pub fn build(self) -> VMWrapper {
         let (etx, erx) = channel();
 
@@ -567,6 +1177,11 @@ impl VMConfig {
             vm: std::ptr::null_mut(),
             classes_v2: RefCell::new(HashMap::new()),
             error_recv: erx,
+            guarded_slots: RefCell::new(vec![]),
+            interpret_timeout: self.interpret_timeout_ms.map(Duration::from_millis),
+            foreign_counts: RefCell::new(HashMap::new()),
+            write_subscribers: Rc::new(RefCell::new(WriteSubscribers::default())),
+            wren_fns: RefCell::default(),
         }));
 
         let vm_config = Box::into_raw(Box::new(UserData {
@@ -606,6 +1221,145 @@ impl VMConfig {
     }
 }
 
+/// A raw VM pointer that can be sent to a [`Watchdog`] thread.
+struct InterruptHandle(*mut WrenVM);
+
+// Safety: only used to call wrenInterrupt, which only sets an atomic flag,
+// and the watchdog is always joined before the VM can be freed
+unsafe impl Send for InterruptHandle {}
+
+impl InterruptHandle {
+    fn interrupt(self) {
+        unsafe { wren_sys::wrenInterrupt(self.0) }
+    }
+}
+
+/// Splits `source` before its last top-level statement, by finding the last newline that isn't
+/// inside brackets, a string or a comment, or continued by an operator or a method call
+fn split_last_statement(source: &str) -> (&str, &str) {
+    let bytes = source.as_bytes();
+    // Open brackets, with `%` standing for an interpolation, which goes back into its string
+    let mut brackets = vec![];
+    // The last character that wasn't whitespace or in a comment, to spot continued lines
+    let mut last = b'\n';
+    let (mut start, mut end) = (0, 0);
+    let mut at_break = true;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\n' => {
+                let next = source[i + 1..].trim_start_matches([' ', '\t', '\r']);
+                let continued = !brackets.is_empty()
+                    || b"+-*/%<>=!&|^~?:,.".contains(&last)
+                    || (next.starts_with('.') && !next.starts_with(".."));
+                at_break |= !continued;
+                i += 1;
+                continue;
+            }
+            b' ' | b'\t' | b'\r' => {
+                i += 1;
+                continue;
+            }
+            b'/' if bytes[i..].starts_with(b"//") => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            b'/' if bytes[i..].starts_with(b"/*") => {
+                // Block comments nest
+                let mut nesting = 0;
+                while i < bytes.len() {
+                    if bytes[i..].starts_with(b"/*") {
+                        nesting += 1;
+                        i += 2;
+                    } else if bytes[i..].starts_with(b"*/") {
+                        nesting -= 1;
+                        i += 2;
+                        if nesting == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+                continue;
+            }
+            _ => {}
+        }
+        if at_break {
+            start = i;
+            at_break = false;
+        }
+        last = bytes[i];
+        i = match bytes[i] {
+            b'"' if bytes[i..].starts_with(b"\"\"\"") => source[i + 3..]
+                .find("\"\"\"")
+                .map_or(bytes.len(), |raw_end| i + 3 + raw_end + 3),
+            b'"' => skip_string(bytes, i + 1, &mut brackets),
+            b'(' | b'[' | b'{' => {
+                brackets.push(bytes[i]);
+                i + 1
+            }
+            b')' | b']' | b'}' => match brackets.pop() {
+                Some(b'%') => skip_string(bytes, i + 1, &mut brackets),
+                _ => i + 1,
+            },
+            _ => i + source[i..].chars().next().map_or(1, char::len_utf8),
+        };
+        end = i;
+    }
+    (&source[..start], &source[start..end])
+}
+
+/// Skips the rest of a string from `i`, up to its closing quote or an interpolation, which is
+/// pushed onto `brackets` to come back to the string when it's closed
+fn skip_string(bytes: &[u8], mut i: usize, brackets: &mut Vec<u8>) -> usize {
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return i + 1,
+            b'%' if bytes.get(i + 1) == Some(&b'(') => {
+                brackets.push(b'%');
+                return i + 2;
+            }
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// Interrupts a VM after a timeout, unless it's dropped first
+struct Watchdog {
+    cancel: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    fn start(vm: *mut WrenVM, timeout: Duration) -> Self {
+        let (cancel, cancelled) = channel();
+        let handle = InterruptHandle(vm);
+        let thread = std::thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = cancelled.recv_timeout(timeout) {
+                handle.interrupt();
+            }
+        });
+        Watchdog {
+            cancel: Some(cancel),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        drop(self.cancel.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// Errors that can happen when sending a foreign object to Wren
 pub enum ForeignSendError {
@@ -632,33 +1386,223 @@ impl std::fmt::Display for ForeignSendError {
 
 impl std::error::Error for ForeignSendError {}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Errors that can happen when registering a function with [`VM::register_wren_fn`]
+pub enum RegisterWrenFnError {
+    /// [`MAX_WREN_FNS`](foreign_v2::MAX_WREN_FNS) methods are already registered with the VM
+    TooManyFunctions,
+}
+
+impl std::fmt::Display for RegisterWrenFnError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RegisterWrenFnError::TooManyFunctions => write!(
+                fmt,
+                "only {} functions can be registered with a VM",
+                foreign_v2::MAX_WREN_FNS
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RegisterWrenFnError {}
+
+/// A borrow of a foreign object living in a slot.
+///
+/// While the guard is alive, the object is kept alive through a handle, so it can't be
+/// collected from under the reference. The slot is locked too: any attempt to overwrite it
+/// through the [`VM`] (or to make a call, which replaces every slot) panics.
+pub struct SlotGuard<'vm, T> {
+    vm: &'vm VM,
+    slot: SlotId,
+    object: &'vm T,
+    _root: Handle<'vm>,
+}
+
+impl<'vm, T> SlotGuard<'vm, T> {
+    /// The slot this guard is locking
+    pub fn slot(&self) -> SlotId {
+        self.slot
+    }
+}
+
+impl<'vm, T> std::ops::Deref for SlotGuard<'vm, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.object
+    }
+}
+
+impl<'vm, T> Drop for SlotGuard<'vm, T> {
+    fn drop(&mut self) {
+        let mut guarded = self.vm.guarded_slots.borrow_mut();
+        if let Some(pos) = guarded.iter().position(|(s, _)| *s == self.slot) {
+            guarded.swap_remove(pos);
+        }
+    }
+}
+
+/// Captures a [`VM`]'s output until it's dropped, see [`VM::subscribe_write`]
+pub struct WriteSubscription {
+    subscribers: Weak<RefCell<WriteSubscribers>>,
+    id: usize,
+}
+
+impl Drop for WriteSubscription {
+    fn drop(&mut self) {
+        if let Some(subscribers) = self.subscribers.upgrade() {
+            let mut subscribers = subscribers.borrow_mut();
+            if let Some(pos) = subscribers
+                .handlers
+                .iter()
+                .position(|(id, _)| *id == self.id)
+            {
+                subscribers.handlers.remove(pos);
+            }
+        }
+    }
+}
+
 impl VM {
+    /// Sends everything Wren writes to `handler` instead of the [`Printer`],
+    /// for as long as the returned subscription is alive.
+    ///
+    /// Subscriptions stack: only the newest one receives output, and dropping it
+    /// hands output back to the one before it (or the [`Printer`], if there's none left).
+    pub fn subscribe_write<F: Fn(&str) + 'static>(&self, handler: F) -> WriteSubscription {
+        let mut subscribers = self.write_subscribers.borrow_mut();
+        let id = subscribers.next_id;
+        subscribers.next_id += 1;
+        subscribers.handlers.push((id, Rc::new(handler)));
+        WriteSubscription {
+            subscribers: Rc::downgrade(&self.write_subscribers),
+            id,
+        }
+    }
+
+    /// The handler that should receive output right now, if any
+    pub(crate) fn write_subscriber(&self) -> Option<WriteHandler> {
+        let subscribers = self.write_subscribers.borrow();
+        subscribers
+            .handlers
+            .last()
+            .map(|(_, handler)| handler.clone())
+    }
+
+    /// Expose a standalone Rust function to Wren as the static method `method` of `class`,
+    /// in `module`.
+    ///
+    /// The class still has to be declared in Wren, with a matching foreign method
+    /// (e.g. `class Math { foreign static sin(x) }`), and the function has to be
+    /// registered before that declaration is interpreted.
+    ///
+    /// Registering another function for the same method replaces it. At most
+    /// [`MAX_WREN_FNS`](foreign_v2::MAX_WREN_FNS) methods can be registered with a VM,
+    /// after which new methods are refused with [`RegisterWrenFnError::TooManyFunctions`].
+    pub fn register_wren_fn<F, Args, Ret, M, C, N>(
+        &self, module: M, class: C, method: N, func: F,
+    ) -> Result<(), RegisterWrenFnError>
+    where
+        F: foreign_v2::IntoWrenFn<Args, Ret>,
+        M: Into<String>,
+        C: Into<String>,
+        N: Into<String>,
+    {
+        let signature = FunctionSignature::new_function(method, F::ARITY).as_wren_string();
+        let key = (module.into(), class.into(), signature);
+        let call: WrenFnCall = Rc::new(move |vm: &VM| func.call_from_vm(vm));
+        let mut wren_fns = self.wren_fns.borrow_mut();
+        match wren_fns.indices.get(&key) {
+            Some(&index) => wren_fns.calls[index] = call,
+            None => {
+                let next = wren_fns.calls.len();
+                if next >= foreign_v2::MAX_WREN_FNS {
+                    return Err(RegisterWrenFnError::TooManyFunctions);
+                }
+                wren_fns.indices.insert(key, next);
+                wren_fns.calls.push(call);
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the function registered for a foreign method, if any
+    pub(crate) fn find_wren_fn(
+        &self, module: &str, class: &str, signature: &str, is_static: bool,
+    ) -> Option<unsafe extern "C" fn(*mut WrenVM)> {
+        if !is_static {
+            return None;
+        }
+        let key = (module.to_string(), class.to_string(), signature.to_string());
+        let index = *self.wren_fns.borrow().indices.get(&key)?;
+        Some(foreign_v2::WREN_FN_CALLS[index])
+    }
+
+    /// The function registered at `index`, see [`foreign_v2::WREN_FN_CALLS`]
+    pub(crate) fn wren_fn(&self, index: usize) -> Option<WrenFnCall> {
+        self.wren_fns.borrow().calls.get(index).cloned()
+    }
+
     // Slot and Handle API
     pub fn ensure_slots(&self, count: usize) {
         unsafe { wren_sys::wrenEnsureSlots(self.vm, count as raw::c_int) }
     }
 
+    /// [`ensure_slots`](VM::ensure_slots), then return the resulting [slot count](VM::get_slot_count)
+    ///
+    /// Wren never shrinks the slot array here, so this can be more than `count`
+    pub fn ensure_slots_info(&self, count: usize) -> usize {
+        self.ensure_slots(count);
+        self.get_slot_count()
+    }
+
+    fn is_guarded(&self, slot: SlotId) -> bool {
+        self.guarded_slots.borrow().iter().any(|(s, _)| *s == slot)
+    }
+
+    fn assert_unguarded(&self, slot: SlotId) {
+        if self.is_guarded(slot) {
+            panic!(
+                "slot {} is locked by a SlotGuard and can't be overwritten",
+                slot
+            );
+        }
+    }
+
     pub fn get_slot_count(&self) -> usize {
         unsafe { wren_sys::wrenGetSlotCount(self.vm) as usize }
     }
 
     pub fn set_slot_bool(&self, slot: SlotId, val: bool) {
         self.ensure_slots(slot + 1);
+        self.assert_unguarded(slot);
         unsafe { wren_sys::wrenSetSlotBool(self.vm, slot as raw::c_int, val) }
     }
 
     pub fn set_slot_double(&self, slot: SlotId, val: f64) {
         self.ensure_slots(slot + 1);
+        self.assert_unguarded(slot);
         unsafe { wren_sys::wrenSetSlotDouble(self.vm, slot as raw::c_int, val) }
     }
 
+    /// Copies the value in `src` into `dst`
+    pub fn copy_slot(&self, dst: SlotId, src: SlotId) {
+        self.ensure_slots(dst.max(src) + 1);
+        self.assert_unguarded(dst);
+        unsafe { wren_sys::wrenCopySlot(self.vm, dst as raw::c_int, src as raw::c_int) }
+    }
+
+    /// Sets `slot` to `null`, which is how a foreign method returns "nothing" to Wren
     pub fn set_slot_null(&self, slot: SlotId) {
         self.ensure_slots(slot + 1);
+        self.assert_unguarded(slot);
         unsafe { wren_sys::wrenSetSlotNull(self.vm, slot as raw::c_int) }
     }
 
     pub fn set_slot_bytes(&self, slot: SlotId, bytes: &[u8]) {
         self.ensure_slots(slot + 1);
+        self.assert_unguarded(slot);
         unsafe {
             wren_sys::wrenSetSlotBytes(
                 self.vm,
@@ -671,6 +1615,7 @@ impl VM {
 
     pub fn set_slot_string<S: AsRef<str>>(&self, slot: SlotId, string: S) {
         self.ensure_slots(slot + 1);
+        self.assert_unguarded(slot);
         let string = string.as_ref();
         unsafe {
             wren_sys::wrenSetSlotBytes(
@@ -720,6 +1665,9 @@ impl VM {
         }
     }
 
+    /// Gets the string in `slot`, or [`None`] if the slot holds anything else (including `null`).
+    ///
+    /// Invalid UTF-8 is replaced lossily, use [`VM::get_slot_bytes`] to get the raw contents.
     pub fn get_slot_string(&self, slot: SlotId) -> Option<String> {
         self.ensure_slots(slot + 1);
         if self.get_slot_type(slot) != SlotType::String {
@@ -755,6 +1703,7 @@ impl VM {
         &self, module: M, name: N, slot: SlotId,
     ) -> bool {
         self.ensure_slots(slot + 1);
+        self.assert_unguarded(slot);
         if !self.has_variable(&module, &name) {
             return false;
         }
@@ -766,6 +1715,21 @@ impl VM {
         true
     }
 
+    /// Reads the variable `name` from `module` as a `T`
+    ///
+    /// The variable is loaded into a fresh slot past the ones in use, so no slot is overwritten.
+    /// Returns None if the variable does not exist, or can't be converted to a `T`
+    pub fn get_variable_typed<T: WrenTryFrom, M: AsRef<str>, N: AsRef<str>>(
+        &self, module: M, name: N,
+    ) -> Option<T> {
+        let slot = self.get_slot_count();
+        self.ensure_slots(slot + 1 + T::SCRATCH_SPACE);
+        if !self.get_variable(module, name, slot) {
+            return None;
+        }
+        T::try_from_vm(self, slot, slot + 1)
+    }
+
     pub fn has_variable<M: AsRef<str>, N: AsRef<str>>(&self, module: M, name: N) -> bool {
         if !self.has_module(&module) {
             return false;
@@ -782,6 +1746,7 @@ impl VM {
 
     pub fn set_slot_new_list(&self, slot: SlotId) {
         self.ensure_slots(slot + 1);
+        self.assert_unguarded(slot);
         unsafe { wren_sys::wrenSetSlotNewList(self.vm, slot as raw::c_int) }
     }
 
@@ -810,6 +1775,7 @@ impl VM {
     pub fn get_list_element(&self, list_slot: SlotId, index: i32, element_slot: SlotId) {
         self.ensure_slots(element_slot + 1);
         self.ensure_slots(list_slot + 1);
+        self.assert_unguarded(element_slot);
         unsafe {
             wren_sys::wrenGetListElement(
                 self.vm,
@@ -835,6 +1801,7 @@ impl VM {
 
     pub fn set_slot_new_map(&self, slot: SlotId) {
         self.ensure_slots(slot + 1);
+        self.assert_unguarded(slot);
         unsafe { wren_sys::wrenSetSlotNewMap(self.vm, slot as raw::c_int) }
     }
 
@@ -867,6 +1834,7 @@ impl VM {
         self.ensure_slots(map_slot + 1);
         self.ensure_slots(key_slot + 1);
         self.ensure_slots(value_slot + 1);
+        self.assert_unguarded(value_slot);
         unsafe {
             wren_sys::wrenGetMapValue(
                 self.vm,
@@ -895,6 +1863,7 @@ impl VM {
         self.ensure_slots(map_slot + 1);
         self.ensure_slots(key_slot + 1);
         self.ensure_slots(removed_value_slot + 1);
+        self.assert_unguarded(removed_value_slot);
         unsafe {
             wren_sys::wrenRemoveMapValue(
                 self.vm,
@@ -910,6 +1879,64 @@ impl VM {
         self.get_slot_foreign_mut(slot).map(|mr| &*mr)
     }
 
+    /// Gets the foreign object in `slot`, but only if it's a `T` and satisfies `predicate`
+    pub fn get_slot_foreign_if<T: 'static + ClassObject, P: FnOnce(&T) -> bool>(
+        &self, slot: SlotId, predicate: P,
+    ) -> Option<&T> {
+        self.get_slot_foreign(slot).filter(|obj| predicate(obj))
+    }
+
+    /// Borrows the foreign object in `slot`, locking the slot until the returned guard is dropped.
+    ///
+    /// Returns [`None`] if the slot doesn't hold a `T`, or is already locked by another guard.
+    pub fn get_slot_guard<T: 'static + ClassObject>(
+        &self, slot: SlotId,
+    ) -> Option<SlotGuard<'_, T>> {
+        if self.is_guarded(slot) {
+            return None;
+        }
+        let object = self.get_slot_foreign(slot)?;
+        self.guarded_slots
+            .borrow_mut()
+            .push((slot, |vm, slot| vm.get_slot_foreign::<T>(slot).is_some()));
+        Some(SlotGuard {
+            vm: self,
+            slot,
+            object,
+            _root: Handle {
+                handle: unsafe { wren_sys::wrenGetSlotHandle(self.vm, slot as raw::c_int) },
+                wvm: self.vm,
+                vm: marker::PhantomData,
+            },
+        })
+    }
+
+    /// How many `T`s are currently alive in this VM, as foreign objects
+    pub fn foreign_object_count<T: 'static>(&self) -> isize {
+        self.foreign_counts
+            .borrow()
+            .get(&TypeId::of::<T>())
+            .map_or(0, |(_, count)| count.get())
+    }
+
+    /// Runs a garbage collection cycle, then checks that the slots locked by [`SlotGuard`]s
+    /// still hold their objects.
+    ///
+    /// The guards keep their objects alive, so this only panics if a locked slot was
+    /// overwritten by going around the [`VM`] (through `wren_sys`).
+    pub fn call_gc_checked(&self) {
+        unsafe { wren_sys::wrenCollectGarbage(self.vm) }
+
+        for (slot, holds_object) in self.guarded_slots.borrow().iter() {
+            assert!(
+                holds_object(self, *slot),
+                "slot {} is locked by a SlotGuard, but no longer holds its object",
+                slot
+            );
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
     pub fn get_slot_foreign_mut<T: 'static + ClassObject>(&self, slot: SlotId) -> Option<&mut T> {
         self.ensure_slots(slot + 1);
         if self.get_slot_type(slot) != SlotType::Foreign {
@@ -918,16 +1945,14 @@ impl VM {
         unsafe {
             let ptr = wren_sys::wrenGetSlotForeign(self.vm, slot as raw::c_int);
             if !ptr.is_null() {
-                let fo = std::ptr::read_unaligned(ptr as *mut ForeignObject<T>);
-                let ret = if fo.type_id == any::TypeId::of::<T>() {
-                    // Safe to downcast
-                    fo.object.as_mut()
+                let fo = ptr as *mut ForeignObject<T>;
+                if std::ptr::addr_of!((*fo).type_id).read_unaligned() == any::TypeId::of::<T>() {
+                    // Safe to downcast, and reached in place, so inline objects work too
+                    ForeignObject::object_ptr(fo).map(|object| &mut *object)
                 } else {
                     // Incorrect type, unsafe to downcast
                     None
-                };
-                std::ptr::write_unaligned(ptr as *mut ForeignObject<T>, fo);
-                ret
+                }
             } else {
                 None
             }
@@ -939,13 +1964,17 @@ impl VM {
     where
         F: FnOnce(&VM, Option<&T::Class>) -> O,
     {
-        let (update, class) = match self.classes_v2.borrow_mut().get_mut(&TypeId::of::<T>()) {
-            Some(cls) => (false, cls.clone()),
+        // Not borrowed while allocating, the allocator might use other classes
+        let existing = self.classes_v2.borrow().get(&TypeId::of::<T>()).cloned();
+        let (update, class) = match existing {
+            Some(cls) => (false, cls),
             None => {
                 use crate::foreign_v2::V2Class;
 
                 // Initialize the class (should be done in case the type is *not* constructable)
-                let class = Rc::new(RefCell::new(Box::new(T::Class::allocate()) as Box<dyn Any>));
+                let class = Rc::new(RefCell::new(
+                    Box::new(T::Class::allocate(self)) as Box<dyn Any>
+                ));
                 (true, class)
             }
         };
@@ -966,13 +1995,17 @@ impl VM {
     where
         F: FnOnce(&VM, Option<&mut T::Class>) -> O,
     {
-        let (update, class) = match self.classes_v2.borrow_mut().get_mut(&TypeId::of::<T>()) {
-            Some(cls) => (false, cls.clone()),
+        // Not borrowed while allocating, the allocator might use other classes
+        let existing = self.classes_v2.borrow().get(&TypeId::of::<T>()).cloned();
+        let (update, class) = match existing {
+            Some(cls) => (false, cls),
             None => {
                 use crate::foreign_v2::V2Class;
 
                 // Initialize the class (should be done in case the type is *not* constructable)
-                let class = Rc::new(RefCell::new(Box::new(T::Class::allocate()) as Box<dyn Any>));
+                let class = Rc::new(RefCell::new(
+                    Box::new(T::Class::allocate(self)) as Box<dyn Any>
+                ));
                 (true, class)
             }
         };
@@ -1002,10 +2035,13 @@ impl VM {
     /// If it's type matches with type T, will create a new instance in the given slot
     ///
     /// WARNING: This *will* overwrite slot `scratch`, so be careful.
+    #[allow(clippy::mut_from_ref)]
     pub fn set_slot_new_foreign_scratch<M: AsRef<str>, C: AsRef<str>, T: 'static + ClassObject>(
         &self, module: M, class: C, object: T, slot: SlotId, scratch: SlotId,
     ) -> Result<&mut T, ForeignSendError> {
         self.ensure_slots(slot.max(scratch) + 1);
+        self.assert_unguarded(slot);
+        self.assert_unguarded(scratch);
         let conf = unsafe {
             std::ptr::read_unaligned(wren_sys::wrenGetUserData(self.vm) as *mut UserData)
         };
@@ -1024,17 +2060,13 @@ impl VM {
                     // The Wren foreign class corresponds with this real object.
                     // We can coerce it and treat this object as that class, even if not instantiated by Wren.
 
-                    // Create the new ForeignObject
-                    let new_obj = ForeignObject {
-                        object: Box::into_raw(Box::new(object)),
-                        type_id: any::TypeId::of::<T>(),
-                    };
-
                     // Load the Wren class object into scratch slot.
-                    self.get_variable(module, class, scratch);
+                    // If it isn't there, scratch is left alone, and may hold some other class
+                    let found = self.get_variable(module, class, scratch);
 
                     // Make sure the class isn't null (undeclared in Wren code)
                     match self.get_slot_type(scratch) {
+                        _ if !found => Err(ForeignSendError::NoWrenClass),
                         SlotType::Null => Err(ForeignSendError::NoWrenClass), // You haven't declared the foreign class to Wren
                         SlotType::Unknown => unsafe {
                             // A Wren class
@@ -1047,14 +2079,21 @@ impl VM {
                             );
 
                             if !wptr.is_null() {
-                                // Move the ForeignObject into the pointer
-                                std::ptr::write_unaligned(wptr as *mut _, new_obj);
+                                // Move the new ForeignObject into the pointer
+                                std::ptr::write_unaligned(
+                                    wptr as *mut _,
+                                    ForeignObject::new(self, object),
+                                );
                             }
 
                             // Reinterpret the pointer as an object if we were successful
-                            match (wptr as *mut ForeignObject<T>).as_mut() {
-                                Some(ptr) => Ok(ptr.object.as_mut().unwrap()),
-                                None => Err(ForeignSendError::NoMemory),
+                            if wptr.is_null() {
+                                Err(ForeignSendError::NoMemory)
+                            } else {
+                                Ok(
+                                    &mut *ForeignObject::object_ptr(wptr as *mut ForeignObject<T>)
+                                        .unwrap(),
+                                )
                             }
                         },
                         _ => Err(ForeignSendError::NoWrenClass),
@@ -1084,6 +2123,30 @@ impl VM {
         }))
     }
 
+    /// Number of modules in the [`ModuleLibrary`] this VM was built with (0 if it has none)
+    pub fn module_count(&self) -> usize {
+        self.with_library(|lib| lib.map_or(0, |lib| lib.modules.len()))
+    }
+
+    /// Number of foreign classes registered to `module` in this VM's [`ModuleLibrary`]
+    pub fn class_count<M: AsRef<str>>(&self, module: M) -> usize {
+        self.with_library(|lib| {
+            lib.and_then(|lib| lib.modules.get(module.as_ref()))
+                .map_or(0, |module| module.classes.len())
+        })
+    }
+
+    fn with_library<R, F: FnOnce(Option<&ModuleLibrary>) -> R>(&self, f: F) -> R {
+        let conf = unsafe {
+            std::ptr::read_unaligned(wren_sys::wrenGetUserData(self.vm) as *mut UserData)
+        };
+        let ret = f(conf.library.as_ref());
+        unsafe {
+            std::ptr::write_unaligned(wrenGetUserData(self.vm) as *mut UserData, conf);
+        }
+        ret
+    }
+
     pub fn abort_fiber(&self, slot: SlotId) {
         unsafe { wren_sys::wrenAbortFiber(self.vm, slot as raw::c_int) }
     }
@@ -1093,12 +2156,41 @@ impl VM {
     }
 }
 
-impl Drop for VM {
-    fn drop(&mut self) {
+impl VM {
+    /// Frees the Wren VM, which finalizes every foreign object it holds.
+    ///
+    /// Returns the foreign objects that were still alive (so only freed by this) as
+    /// "`<count>` `<type>`".
+    fn free_vm(&mut self) -> Vec<String> {
+        if self.vm.is_null() {
+            return vec![];
+        }
+        let mut live: Vec<_> = self
+            .foreign_counts
+            .borrow()
+            .values()
+            .filter(|(_, count)| count.get() != 0)
+            .map(|(name, count)| format!("{} {}", count.get(), name))
+            .collect();
+        live.sort();
         unsafe {
             let conf = wren_sys::wrenGetUserData(self.vm);
             let _: Box<UserData> = Box::from_raw(conf as *mut _); // Drop the userdata
             wren_sys::wrenFreeVM(self.vm);
         }
+        self.vm = std::ptr::null_mut();
+        live
+    }
+}
+
+impl Drop for VM {
+    fn drop(&mut self) {
+        let live = self.free_vm();
+        if cfg!(debug_assertions) && !live.is_empty() {
+            eprintln!(
+                "ruwren: foreign objects were still alive when their VM was dropped: {}",
+                live.join(", ")
+            );
+        }
     }
 }
@@ -80,8 +80,17 @@ pub extern "C" fn wren_error(
 pub extern "C" fn wren_print(vm: *mut WrenVM, message: *const raw::c_char) {
     let conf = unsafe { &mut *(wren_sys::wrenGetUserData(vm) as *mut UserData) };
     let message_str = unsafe { ffi::CStr::from_ptr(message) };
-    conf.printer
-        .print(message_str.to_string_lossy().to_string());
+    // Cloned out first, so the handler is free to (un)subscribe
+    let subscriber = conf
+        .vm
+        .upgrade()
+        .and_then(|vm| vm.try_borrow().ok().and_then(|vm| vm.write_subscriber()));
+    match subscriber {
+        Some(handler) => handler(&message_str.to_string_lossy()),
+        None => conf
+            .printer
+            .print(message_str.to_string_lossy().to_string()),
+    }
 }
 
 pub extern "C" fn wren_bind_foreign_method(
@@ -93,24 +102,31 @@ pub extern "C" fn wren_bind_foreign_method(
     let class = unsafe { ffi::CStr::from_ptr(class) };
     let signature = unsafe { ffi::CStr::from_ptr(sgn) };
 
-    if let Some(ref library) = conf.library {
+    let from_library = if let Some(ref library) = conf.library {
         if let Some(rc) =
             library.get_foreign_class(module.to_string_lossy(), class.to_string_lossy())
         {
             rc.methods
-                .function_pointers
-                .iter()
-                .find(|mp| {
-                    mp.signature.as_wren_string() == signature.to_string_lossy()
-                        && mp.is_static == is_static
-                })
+                .find(is_static, &signature.to_string_lossy())
                 .map(|mp| mp.pointer)
         } else {
             None
         }
     } else {
         None
-    }
+    };
+
+    // Fall back on standalone functions registered with the VM
+    from_library.or_else(|| {
+        let vm = conf.vm.upgrade()?;
+        let vm = vm.try_borrow().ok()?;
+        vm.find_wren_fn(
+            &module.to_string_lossy(),
+            &class.to_string_lossy(),
+            &signature.to_string_lossy(),
+            is_static,
+        )
+    })
 }
 
 pub extern "C" fn wren_bind_foreign_class(
@@ -287,3 +287,815 @@ fn foreign_instance() {
         assert_eq!(vm.get_slot_double(0), Some(21.45));
     });
 }
+
+#[test]
+fn test_call_function_by_handle() {
+    use super::foreign_v2::WrenValue;
+
+    let vm = VMConfig::new().build();
+
+    let source = vm.interpret(
+        "main",
+        r"
+    class GameEngine {
+        static scale(value, factor) {
+            return value * factor
+        }
+
+        static pair(value) {
+            return [value, value]
+        }
+    }
+    ",
+    );
+    assert!(source.is_ok());
+
+    let handle = vm.make_call_handle(super::FunctionSignature::new_function("scale", 2));
+    for i in 0..3 {
+        vm.execute(|vm| {
+            vm.ensure_slots(4);
+            vm.get_variable("main", "GameEngine", 3);
+        });
+        let res = vm.call_function_by_handle(
+            3,
+            &handle,
+            &[WrenValue::Number(i as f64), WrenValue::Number(2.0)],
+        );
+        assert_eq!(res.unwrap(), WrenValue::Number(i as f64 * 2.0));
+    }
+
+    let handle = vm.make_call_handle(super::FunctionSignature::new_function("pair", 1));
+    vm.execute(|vm| vm.get_variable("main", "GameEngine", 0));
+    match vm.call_function_by_handle(0, &handle, &[WrenValue::Number(1.0)]) {
+        Err(super::VMError::TypeMismatch { got, .. }) => assert_eq!(got, super::SlotType::List),
+        res => panic!("expected a type mismatch, got {:?}", res),
+    }
+}
+
+fn guarded_point_vm() -> super::VMWrapper {
+    let mut lib = super::ModuleLibrary::new();
+    main::publish_module(&mut lib);
+    let vm = VMConfig::new().library(&lib).build();
+    let source = vm.interpret(
+        "main",
+        "
+    foreign class RawPoint {
+        construct new(x) {}
+
+        foreign x()
+        foreign set_x(val)
+    }
+
+    var Origin = RawPoint.new(4)
+    ",
+    );
+    assert!(source.is_ok());
+    vm
+}
+
+#[test]
+fn test_slot_guard() {
+    let vm = guarded_point_vm();
+    vm.execute(|vm| {
+        vm.ensure_slots(2);
+        vm.get_variable("main", "Origin", 0);
+        {
+            let point = vm.get_slot_guard::<Point>(0).unwrap();
+            assert_eq!(point.x, 4.0);
+            assert!(vm.get_slot_guard::<Point>(0).is_none());
+            vm.set_slot_double(1, 2.0);
+        }
+        assert!(vm.get_slot_guard::<Point>(0).is_some());
+        vm.set_slot_double(0, 2.0);
+        assert!(vm.get_slot_guard::<Point>(0).is_none());
+    });
+}
+
+#[test]
+#[should_panic(expected = "locked by a SlotGuard")]
+fn test_slot_guard_blocks_overwrite() {
+    let vm = guarded_point_vm();
+    vm.execute(|vm| {
+        vm.get_variable("main", "Origin", 0);
+        let _point = vm.get_slot_guard::<Point>(0).unwrap();
+        vm.set_slot_null(0);
+    });
+}
+
+#[test]
+#[should_panic(expected = "slot 0 is locked by a SlotGuard, and a call would replace it")]
+fn test_slot_guard_blocks_call() {
+    let vm = guarded_point_vm();
+    let handle = vm.make_call_handle(super::FunctionSignature::new_function("x", 0));
+    vm.execute(|inner| {
+        inner.get_variable("main", "Origin", 0);
+        let _point = inner.get_slot_guard::<Point>(0).unwrap();
+        let _ = vm.call_handle(&handle);
+    });
+}
+
+#[test]
+fn test_get_slot_foreign_if() {
+    let vm = guarded_point_vm();
+    vm.execute(|vm| {
+        vm.get_variable("main", "Origin", 0);
+        assert!(vm
+            .get_slot_foreign_if::<Point, _>(0, |p| p.x > 0.0)
+            .is_some());
+        assert!(vm
+            .get_slot_foreign_if::<Point, _>(0, |p| p.x < 0.0)
+            .is_none());
+        assert!(vm.get_slot_foreign_if::<Math, _>(0, |_| true).is_none());
+    });
+}
+
+#[test]
+fn test_interpret_timeout() {
+    let vm = VMConfig::new().interpret_timeout_ms(50).build();
+    let res = vm.interpret(
+        "main",
+        "
+    var fiber = Fiber.new {
+        while (true) {}
+    }
+    while (true) {
+        fiber.try()
+    }
+    ",
+    );
+    match res {
+        Err(super::VMError::Runtime { error, .. }) => {
+            assert_eq!(error, "Interrupted by the host.")
+        }
+        other => panic!("expected the script to be interrupted, got {:?}", other),
+    }
+
+    // The interrupt doesn't leak into the next run
+    assert!(vm.interpret("main", "var Done = true").is_ok());
+}
+
+#[test]
+fn test_get_slot_string_non_string() {
+    let vm = VMConfig::new().build();
+    vm.execute(|vm| {
+        vm.ensure_slots(3);
+        vm.set_slot_null(0);
+        vm.set_slot_double(1, 1.0);
+        vm.set_slot_string(2, "wren");
+        assert_eq!(vm.get_slot_string(0), None);
+        assert_eq!(vm.get_slot_string(1), None);
+        assert_eq!(vm.get_slot_string(2).as_deref(), Some("wren"));
+    });
+}
+
+#[test]
+fn test_import_module() {
+    struct TestLoader;
+
+    impl super::ModuleScriptLoader for TestLoader {
+        fn load_script(&mut self, name: String) -> Option<String> {
+            if name == "registry" {
+                Some("var Loaded = true".into())
+            } else {
+                None
+            }
+        }
+    }
+
+    let vm = VMConfig::new().script_loader(TestLoader).build();
+    vm.execute(|vm| assert!(!vm.has_module("registry")));
+    assert!(vm.import_module("registry").is_ok());
+    vm.execute(|vm| {
+        assert!(vm.has_variable("registry", "Loaded"));
+        assert!(!vm.has_variable("main", "Loaded"));
+    });
+    assert!(vm.import_module("missing").is_err());
+}
+
+#[test]
+fn test_foreign_object_count() {
+    let vm = guarded_point_vm();
+    vm.execute(|vm| assert_eq!(vm.foreign_object_count::<Point>(), 1));
+    vm.interpret("main", "var points = [RawPoint.new(1), RawPoint.new(2)]")
+        .unwrap();
+    vm.execute(|vm| assert_eq!(vm.foreign_object_count::<Point>(), 3));
+    vm.interpret("main", "points = null").unwrap();
+    vm.collect_garbage();
+    vm.execute(|vm| {
+        assert_eq!(vm.foreign_object_count::<Point>(), 1);
+        assert_eq!(vm.foreign_object_count::<Math>(), 0);
+    });
+}
+
+#[test]
+fn test_foreign_object_storage() {
+    use super::ForeignObject;
+    use std::rc::Rc;
+
+    type Large = (Rc<()>, [u64; 4]);
+    const _: () = assert!(ForeignObject::<Rc<()>>::INLINE);
+    const _: () = assert!(!ForeignObject::<Large>::INLINE);
+
+    let vm = VMConfig::new().build();
+    let shared = Rc::new(());
+    vm.execute(|vm| {
+        let mut small = ForeignObject::new(vm, Rc::clone(&shared));
+        let mut large: ForeignObject<Large> = ForeignObject::new(vm, (Rc::clone(&shared), [0; 4]));
+        assert_eq!(Rc::strong_count(&shared), 3);
+        assert!(Rc::ptr_eq(small.object_mut().unwrap(), &shared));
+        assert!(Rc::ptr_eq(&large.object_mut().unwrap().0, &shared));
+        assert_eq!(format!("{:?}", small), "ForeignObject { object: () }");
+        assert_eq!(
+            format!("{:?}", large),
+            "ForeignObject { object: ((), [0, 0, 0, 0]) }"
+        );
+
+        unsafe {
+            small.finalize();
+            large.finalize();
+            // Already finalized, so nothing is dropped twice
+            small.finalize();
+        }
+        assert!(small.object_mut().is_none());
+        assert_eq!(
+            format!("{:?}", small),
+            "ForeignObject { object: <finalized> }"
+        );
+        assert_eq!(Rc::strong_count(&shared), 1);
+        assert_eq!(vm.foreign_object_count::<Rc<()>>(), 0);
+        assert_eq!(vm.foreign_object_count::<Large>(), 0);
+    });
+}
+
+#[test]
+fn test_foreign_objects_alive_at_drop() {
+    let mut lib = super::ModuleLibrary::new();
+    main::publish_module(&mut lib);
+    let vm = VMConfig::new().library(&lib).build();
+    vm.interpret(
+        "main",
+        "foreign class RawPoint {\n construct new(x) {}\n}\nvar Held = RawPoint.new(1)",
+    )
+    .unwrap();
+
+    // Counted before the VM is freed, which finalizes them
+    let live = vm.0.borrow_mut().free_vm();
+    assert_eq!(live, vec!["1 ruwren::tests::Point".to_string()]);
+    vm.execute(|vm| assert_eq!(vm.foreign_object_count::<Point>(), 0));
+    // Already freed, so dropping the VM doesn't free it again
+    drop(vm);
+}
+
+#[test]
+fn test_foreign_object_drop() {
+    use super::ForeignObject;
+    use std::rc::Rc;
+
+    let vm = VMConfig::new().build();
+    let shared = Rc::new(());
+    // Never handed to Wren, so dropping it finalizes it
+    vm.execute(|vm| {
+        let _ = ForeignObject::new(vm, Rc::clone(&shared));
+        assert_eq!(vm.foreign_object_count::<Rc<()>>(), 0);
+    });
+    assert_eq!(Rc::strong_count(&shared), 1);
+
+    // Even once the VM is gone
+    let object = vm.execute(|vm| ForeignObject::new(vm, Rc::clone(&shared)));
+    drop(vm);
+    drop(object);
+    assert_eq!(Rc::strong_count(&shared), 1);
+}
+
+#[test]
+fn test_integer_conversion_bounds() {
+    use super::foreign_v2::WrenAtom;
+
+    fn convert<T: WrenAtom>(vm: &super::VM, value: f64) -> Option<T> {
+        vm.set_slot_double(0, value);
+        T::from_vm(vm, 0, 1)
+    }
+
+    const TWO_POW_63: f64 = 9_223_372_036_854_775_808.0;
+    const TWO_POW_64: f64 = 18_446_744_073_709_551_616.0;
+
+    let vm = VMConfig::new().build();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        assert_eq!(convert::<i64>(vm, TWO_POW_63), None);
+        assert_eq!(convert::<i64>(vm, -TWO_POW_63), Some(i64::MIN));
+        // The largest double below 2^63
+        assert_eq!(
+            convert::<i64>(vm, 9_223_372_036_854_774_784.0),
+            Some(9_223_372_036_854_774_784)
+        );
+        assert_eq!(convert::<u64>(vm, TWO_POW_64), None);
+        assert_eq!(convert::<usize>(vm, TWO_POW_64), None);
+        assert_eq!(convert::<u64>(vm, TWO_POW_63), Some(1 << 63));
+        assert_eq!(convert::<u64>(vm, -1.0), None);
+        assert_eq!(convert::<u8>(vm, 255.0), Some(255));
+        assert_eq!(convert::<u8>(vm, 256.0), None);
+        // Truncated, like an `as` cast
+        assert_eq!(convert::<i32>(vm, 1.5), Some(1));
+        assert_eq!(convert::<i32>(vm, -1.5), Some(-1));
+        assert_eq!(convert::<u8>(vm, 255.5), Some(255));
+        assert_eq!(convert::<u8>(vm, -0.5), Some(0));
+        assert_eq!(convert::<i32>(vm, -2.0), Some(-2));
+        assert_eq!(convert::<i32>(vm, f64::NAN), None);
+    });
+}
+
+#[test]
+fn test_eval_expression_typed() {
+    use super::foreign_v2::WrenString;
+
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "var Base = 40").unwrap();
+
+    assert_eq!(
+        vm.eval_expression_typed::<f64, _>("Base + 2").unwrap(),
+        42.0
+    );
+    assert!(vm.eval_expression_typed::<bool, _>("Base > 2").unwrap());
+    let greeting = vm
+        .eval_expression_typed::<WrenString, _>("\"hello\" + \" wren\"")
+        .unwrap();
+    assert_eq!(greeting.into_string().unwrap(), "hello wren");
+
+    match vm.eval_expression_typed::<f64, _>("\"not a number\"") {
+        Err(super::VMError::TypeMismatch { got, .. }) => {
+            assert_eq!(got, super::SlotType::String)
+        }
+        other => panic!("expected a type mismatch, got {:?}", other.map(|_| ())),
+    }
+    assert!(vm.eval_expression_typed::<f64, _>("Missing + 1").is_err());
+
+    // Nothing is defined in the module, so any name is fair game
+    vm.interpret("main", "var ruwrenEvalResult = 1").unwrap();
+    assert_eq!(
+        vm.eval_expression_typed::<f64, _>("ruwrenEvalResult + Base")
+            .unwrap(),
+        41.0
+    );
+}
+
+#[test]
+fn test_integer_conversions() {
+    let vm = VMConfig::new().build();
+
+    assert_eq!(vm.eval_expression_typed::<usize, _>("3").unwrap(), 3);
+    assert_eq!(vm.eval_expression_typed::<i32, _>("-7").unwrap(), -7);
+    assert_eq!(vm.eval_expression_typed::<isize, _>("-7").unwrap(), -7);
+    assert_eq!(vm.eval_expression_typed::<u8, _>("255").unwrap(), 255);
+    assert!(vm.eval_expression_typed::<u8, _>("256").is_err());
+    assert!(vm.eval_expression_typed::<u32, _>("-1").is_err());
+    assert!(vm.eval_expression_typed::<i64, _>("0/0").is_err());
+    assert_eq!(vm.eval_expression_typed::<f32, _>("1.5").unwrap(), 1.5);
+}
+
+#[test]
+fn test_get_variable_typed() {
+    use super::foreign_v2::WrenString;
+
+    let vm = VMConfig::new().build();
+    vm.interpret("main", "var Count = 3\nvar Name = \"wren\"")
+        .unwrap();
+
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.set_slot_double(0, 42.0);
+        assert_eq!(
+            vm.get_variable_typed::<usize, _, _>("main", "Count"),
+            Some(3)
+        );
+        let name = vm.get_variable_typed::<WrenString, _, _>("main", "Name");
+        assert_eq!(name.unwrap().into_string().unwrap(), "wren");
+        assert!(vm
+            .get_variable_typed::<WrenString, _, _>("main", "Count")
+            .is_none());
+        assert_eq!(vm.get_variable_typed::<f64, _, _>("main", "Missing"), None);
+        assert_eq!(vm.get_variable_typed::<f64, _, _>("other", "Count"), None);
+        assert_eq!(vm.get_slot_double(0), Some(42.0));
+    });
+}
+
+#[test]
+fn test_set_slot_foreign_existing() {
+    let vm = guarded_point_vm();
+    vm.execute(|vm| vm.get_variable("main", "Origin", 0));
+    let handle = vm.get_slot_handle(0);
+
+    assert!(vm.set_slot_foreign_existing::<Point>(1, &handle).is_ok());
+    vm.execute(|vm| {
+        let original = vm.get_slot_foreign::<Point>(0).unwrap() as *const Point;
+        let placed = vm.get_slot_foreign::<Point>(1).unwrap() as *const Point;
+        assert_eq!(original, placed);
+        assert_eq!(vm.foreign_object_count::<Point>(), 1);
+    });
+
+    assert_eq!(
+        vm.set_slot_foreign_existing::<Math>(2, &handle),
+        Err(super::ForeignSendError::ClassMismatch)
+    );
+    vm.execute(|vm| assert_eq!(vm.get_slot_type(2), super::SlotType::Null));
+}
+
+#[test]
+fn test_sorted_class_pointers() {
+    use super::ClassObject;
+
+    let pointers = Math::generate_pointers().sorted();
+    let names: Vec<_> = pointers
+        .function_pointers
+        .iter()
+        .map(|mp| mp.signature.name())
+        .collect();
+    assert_eq!(names, ["add5", "opposite_points", "pointy"]);
+
+    let add5 = pointers.find(true, "add5(_)").unwrap();
+    assert_eq!(
+        add5.signature,
+        super::FunctionSignature::new_function("add5", 1)
+    );
+    assert!(pointers.find(false, "add5(_)").is_none());
+    assert!(pointers.find(true, "add5(_,_)").is_none());
+}
+
+#[test]
+fn test_function_signature_accessors() {
+    let function = super::FunctionSignature::new_function("scale", 2);
+    assert_eq!((function.name(), function.arity()), ("scale", 2));
+    let getter = super::FunctionSignature::new_getter("x");
+    assert_eq!((getter.name(), getter.arity()), ("x", 0));
+    let setter = super::FunctionSignature::new_setter("x");
+    assert_eq!((setter.name(), setter.arity()), ("x", 1));
+    let subscript = super::FunctionSignature::new_subscript_getter();
+    assert_eq!(subscript.as_wren_string(), "[_]");
+    assert_eq!(subscript.arity(), 1);
+    let subscript = super::FunctionSignature::new_subscript_setter();
+    assert_eq!(subscript.as_wren_string(), "[_]=(_)");
+    assert_eq!(subscript.arity(), 2);
+}
+
+#[test]
+fn test_function_signature_ordering() {
+    use super::FunctionSignature;
+    use std::collections::HashSet;
+
+    let mut sigs = [
+        FunctionSignature::new_function("update", 1),
+        FunctionSignature::new_setter("x"),
+        FunctionSignature::new_function("x", 0),
+        FunctionSignature::new_getter("x"),
+        FunctionSignature::new_function("add", 2),
+    ];
+    sigs.sort();
+    let sorted: Vec<_> = sigs.iter().map(|sig| sig.as_wren_string()).collect();
+    assert_eq!(sorted, ["add(_,_)", "update(_)", "x()", "x", "x=(_)"]);
+
+    let unique: HashSet<_> = sigs.iter().cloned().chain(sigs.iter().cloned()).collect();
+    assert_eq!(unique.len(), sigs.len());
+    assert_eq!(
+        FunctionSignature::new_getter("x"),
+        FunctionSignature::new_getter("x")
+    );
+    assert_ne!(
+        FunctionSignature::new_getter("x"),
+        FunctionSignature::new_function("x", 0)
+    );
+}
+
+#[test]
+fn test_subscribe_write() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let vm = VMConfig::new().printer(|_: String| {}).build();
+    let outer = Rc::new(RefCell::new(String::new()));
+    let inner = Rc::new(RefCell::new(String::new()));
+
+    let outer_sub = {
+        let outer = outer.clone();
+        vm.subscribe_write(move |s| outer.borrow_mut().push_str(s))
+    };
+    vm.interpret("main", "System.print(\"one\")").unwrap();
+    {
+        let inner = inner.clone();
+        let _inner_sub = vm.subscribe_write(move |s| inner.borrow_mut().push_str(s));
+        vm.interpret("main", "System.print(\"two\")").unwrap();
+    }
+    vm.interpret("main", "System.print(\"three\")").unwrap();
+    drop(outer_sub);
+    vm.interpret("main", "System.print(\"four\")").unwrap();
+
+    assert_eq!(*outer.borrow(), "one\nthree\n");
+    assert_eq!(*inner.borrow(), "two\n");
+}
+
+#[test]
+fn test_register_wren_fn() {
+    let vm = VMConfig::new().build();
+    let offset = 10.0;
+    vm.execute(|vm| {
+        vm.register_wren_fn("main", "Util", "hypot", |a: f64, b: f64| a.hypot(b))
+            .unwrap();
+        vm.register_wren_fn("main", "Util", "shift", move |a: f64| a + offset)
+            .unwrap();
+        vm.register_wren_fn("main", "Util", "answer", || 42u32)
+            .unwrap();
+        // The same closure type, bound to different methods
+        for (method, step) in [("up", 1.0), ("down", -1.0)] {
+            vm.register_wren_fn("main", "Util", method, move |a: f64| a + step)
+                .unwrap();
+        }
+        // Replaces the first shift
+        vm.register_wren_fn("main", "Util", "shift", move |a: f64| a + 2.0 * offset)
+            .unwrap();
+    });
+
+    vm.interpret(
+        "main",
+        r#"
+    class Util {
+        foreign static hypot(a, b)
+        foreign static shift(a)
+        foreign static answer()
+        foreign static up(a)
+        foreign static down(a)
+    }
+    var Hypot = Util.hypot(3, 4)
+    var Shifted = Util.shift(1)
+    var Answer = Util.answer()
+    var Up = Util.up(5)
+    var Down = Util.down(5)
+    var Error = Fiber.new { Util.shift("one") }.try()
+    "#,
+    )
+    .unwrap();
+
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Hypot", 0);
+        assert_eq!(vm.get_slot_double(0), Some(5.0));
+        vm.get_variable("main", "Shifted", 0);
+        assert_eq!(vm.get_slot_double(0), Some(21.0));
+        vm.get_variable("main", "Answer", 0);
+        assert_eq!(vm.get_slot_double(0), Some(42.0));
+        vm.get_variable("main", "Up", 0);
+        assert_eq!(vm.get_slot_double(0), Some(6.0));
+        vm.get_variable("main", "Down", 0);
+        assert_eq!(vm.get_slot_double(0), Some(4.0));
+        vm.get_variable("main", "Error", 0);
+        assert_eq!(
+            vm.get_slot_string(0).as_deref(),
+            Some("failed to get value of type f64 for slot 1")
+        );
+    });
+}
+
+#[test]
+fn test_register_wren_fn_limit() {
+    use super::{foreign_v2::MAX_WREN_FNS, RegisterWrenFnError};
+
+    let vm = VMConfig::new().build();
+    vm.execute(|vm| {
+        for i in 0..MAX_WREN_FNS {
+            vm.register_wren_fn("main", "Many", format!("f{}", i), move || i as f64)
+                .unwrap();
+        }
+        assert_eq!(
+            vm.register_wren_fn("main", "Many", "extra", || 0.0),
+            Err(RegisterWrenFnError::TooManyFunctions)
+        );
+        // Replacing a registered method doesn't need a new one
+        vm.register_wren_fn("main", "Many", "f0", || -1.0).unwrap();
+    });
+
+    vm.interpret(
+        "main",
+        "class Many {\n foreign static f0()\n}\nvar First = Many.f0()",
+    )
+    .unwrap();
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "First", 0);
+        assert_eq!(vm.get_slot_double(0), Some(-1.0));
+    });
+    // The refused method was never bound
+    assert!(vm
+        .interpret("main", "class Extra {\n foreign static extra()\n}")
+        .is_err());
+}
+
+#[test]
+fn test_wren_error_display() {
+    use super::WrenError;
+
+    assert_eq!(
+        WrenError::Compile("main".into(), 3, "Expect ')'.".into()).to_string(),
+        "wren compile error at main:3: Expect ')'."
+    );
+    assert_eq!(
+        WrenError::Compile(String::new(), 0, "Expect ')'.".into()).to_string(),
+        "wren compile error: Expect ')'."
+    );
+    assert_eq!(
+        WrenError::Runtime("Null does not implement 'foo'.".into()).to_string(),
+        "wren runtime error: Null does not implement 'foo'."
+    );
+    assert_eq!(
+        WrenError::StackTrace("main".into(), 7, "update(_)".into()).to_string(),
+        "wren stack frame at main:7: update(_)"
+    );
+}
+
+#[test]
+fn test_call_gc_checked() {
+    let vm = guarded_point_vm();
+    vm.interpret("main", "for (i in 0...3) RawPoint.new(i)")
+        .unwrap();
+    vm.execute(|vm| {
+        assert_eq!(vm.foreign_object_count::<Point>(), 4);
+        vm.get_variable("main", "Origin", 0);
+        let origin = vm.get_slot_guard::<Point>(0).unwrap();
+        vm.call_gc_checked();
+        assert_eq!(vm.foreign_object_count::<Point>(), 1);
+        assert_eq!(origin.x, 4.0);
+    });
+}
+
+#[test]
+#[should_panic(expected = "slot 0 is locked by a SlotGuard, but no longer holds its object")]
+fn test_call_gc_checked_lost_object() {
+    let vm = guarded_point_vm();
+    vm.execute(|vm| {
+        vm.get_variable("main", "Origin", 0);
+        let _origin = vm.get_slot_guard::<Point>(0).unwrap();
+        // Going around the guard
+        unsafe { super::wren_sys::wrenSetSlotNull(vm.vm, 0) };
+        vm.call_gc_checked();
+    });
+}
+
+#[test]
+fn test_slot_guard_keeps_object_alive() {
+    let vm = guarded_point_vm();
+    vm.interpret("main", "var Make = Fn.new { RawPoint.new(7) }")
+        .unwrap();
+    let call = vm.make_call_handle(super::FunctionSignature::new_function("call", 0));
+    vm.execute(|vm| vm.get_variable("main", "Make", 0));
+    vm.call_handle(&call).unwrap();
+    vm.execute(|vm| {
+        // Slot 0 holds the only reference to the new point
+        let point = vm.get_slot_guard::<Point>(0).unwrap();
+        unsafe {
+            super::wren_sys::wrenSetSlotNull(vm.vm, 0);
+            super::wren_sys::wrenCollectGarbage(vm.vm);
+        }
+        assert_eq!(vm.foreign_object_count::<Point>(), 2);
+        assert_eq!(point.x, 7.0);
+        drop(point);
+        unsafe { super::wren_sys::wrenCollectGarbage(vm.vm) };
+        assert_eq!(vm.foreign_object_count::<Point>(), 1);
+    });
+}
+
+#[test]
+fn test_module_library_merge() {
+    use super::{ConflictError, MergePolicy, Module, ModuleLibrary};
+
+    let plugin = || {
+        let mut lib = ModuleLibrary::new();
+        let mut shared = Module::new();
+        shared.class::<Point, _>("Math");
+        lib.module("main", shared);
+        let mut extra = Module::new();
+        extra.class::<Point, _>("RawPoint");
+        lib.module("extra", extra);
+        lib
+    };
+    let math_type = || std::any::TypeId::of::<Math>();
+
+    let mut lib = ModuleLibrary::new();
+    main::publish_module(&mut lib);
+    assert_eq!(
+        lib.merge(plugin(), MergePolicy::ErrorOnConflict),
+        Err(vec![ConflictError {
+            module: "main".into(),
+            class: "Math".into()
+        }])
+    );
+    assert!(lib.get_foreign_class("extra", "RawPoint").is_none());
+
+    assert_eq!(lib.merge(plugin(), MergePolicy::SkipOnConflict), Ok(()));
+    assert_eq!(
+        lib.get_foreign_class("main", "Math").unwrap().type_id,
+        math_type()
+    );
+    assert!(lib.get_foreign_class("extra", "RawPoint").is_some());
+
+    assert_eq!(
+        lib.merge(plugin(), MergePolicy::OverwriteOnConflict),
+        Ok(())
+    );
+    assert_ne!(
+        lib.get_foreign_class("main", "Math").unwrap().type_id,
+        math_type()
+    );
+}
+
+#[test]
+fn test_module_and_class_exists() {
+    use super::{Module, ModuleLibrary};
+
+    let mut module = Module::new();
+    assert!(!module.class_exists("Point"));
+    module.class::<Point, _>("Point");
+    assert!(module.class_exists("Point"));
+    assert!(!module.class_exists("Math"));
+
+    let mut lib = ModuleLibrary::new();
+    assert!(!lib.module_exists("main"));
+    lib.module("main", module);
+    assert!(lib.module_exists("main"));
+    assert!(!lib.module_exists("extra"));
+}
+
+#[test]
+fn test_interpret_and_capture() {
+    let vm = VMConfig::new().build();
+    assert_eq!(
+        vm.interpret_and_capture::<f64, _, _>("calc", "1 + 2")
+            .unwrap(),
+        3.0
+    );
+    assert_eq!(
+        vm.interpret_and_capture::<f64, _, _>(
+            "calc",
+            "
+    var base = 40
+    var offset = 2
+
+    base + offset
+    "
+        )
+        .unwrap(),
+        42.0
+    );
+    assert!(vm
+        .interpret_and_capture::<bool, _, _>("calc", "base")
+        .is_err());
+
+    // The last statement can span lines, through brackets, operators and method chains
+    assert_eq!(
+        vm.interpret_and_capture::<f64, _, _>(
+            "calc",
+            r#"
+    var list = [
+      1, 2
+    ]
+    var label = "last
+    line: %(list.count)" // not a statement break
+    /* nor
+       this */
+    list.reduce(base) {|a, b| a + b } +
+      offset
+      .abs + label.count
+    "#
+        )
+        .unwrap(),
+        61.0
+    );
+    match vm.interpret_and_capture::<f64, _, _>("calc", "var a = 1\n\na.missing") {
+        Err(super::VMError::Runtime { frames, .. }) => assert_eq!(frames[0].line, 3),
+        other => panic!("expected a runtime error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_module_and_class_count() {
+    let vm = VMConfig::new().build();
+    vm.execute(|vm| {
+        assert_eq!(vm.module_count(), 0);
+        assert_eq!(vm.class_count("main"), 0);
+    });
+
+    let mut lib = super::ModuleLibrary::new();
+    main::publish_module(&mut lib);
+    let vm = VMConfig::new().library(&lib).build();
+    vm.execute(|vm| {
+        assert_eq!(vm.module_count(), 1);
+        assert_eq!(vm.class_count("main"), 2);
+        assert_eq!(vm.class_count("missing"), 0);
+    });
+}
+
+#[test]
+fn test_ensure_slots_info() {
+    let vm = VMConfig::new().build();
+    vm.execute(|vm| {
+        assert_eq!(vm.ensure_slots_info(4), 4);
+        assert_eq!(vm.ensure_slots_info(2), 4);
+        assert_eq!(vm.get_slot_count(), 4);
+    });
+}
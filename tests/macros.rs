@@ -0,0 +1,1964 @@
+use ruwren::{
+    foreign_v2::WrenString, wren_impl, wren_mock, wren_module, wren_module_v2, wren_test,
+    ModuleBuildError, ModuleLibrary, SlotId, SlotType, VMConfig, VMError, VMWrapper, WrenFrom,
+    WrenObject, VM,
+};
+
+mod classes {
+    use super::*;
+
+    #[derive(WrenObject, Default)]
+    pub struct Calculator;
+
+    #[wren_impl]
+    impl Calculator {
+        fn add(&self, a: f64, b: f64) -> f64 {
+            a + b
+        }
+
+        fn nothing(&self) {}
+
+        fn greet(&self, name: WrenString) -> String {
+            format!("Hello, {}!", name.into_string().unwrap())
+        }
+
+        fn sign(&self, value: f64) -> &'static str {
+            if value < 0.0 {
+                "negative"
+            } else {
+                "positive"
+            }
+        }
+
+        fn sum(&self, values: Vec<f64>) -> f64 {
+            values.iter().sum()
+        }
+
+        fn pace(&self, runner: Runner, distance: Meters, time: Minutes) -> String {
+            format!("{} ran {}m in {}s", runner.name, distance.0, time.seconds)
+        }
+
+        fn join(&self, words: Vec<WrenString>, sep: WrenString) -> String {
+            let words: Vec<_> = words
+                .into_iter()
+                .map(|w| w.into_string().unwrap())
+                .collect();
+            words.join(&sep.into_string().unwrap())
+        }
+
+        fn divide(&self, a: f64, b: f64) -> Result<f64, DivideByZero> {
+            if b == 0.0 {
+                Err(DivideByZero)
+            } else {
+                Ok(a / b)
+            }
+        }
+
+        fn safe_sqrt(&self, a: f64) -> Option<f64> {
+            (a >= 0.0).then(|| a.sqrt())
+        }
+
+        fn boxed(&self, a: f64) -> Box<f64> {
+            Box::new(a)
+        }
+
+        fn shared(&self) -> std::rc::Rc<String> {
+            std::rc::Rc::new("shared".to_string())
+        }
+
+        fn pair(&self) -> std::sync::Arc<Vec<f64>> {
+            std::sync::Arc::new(vec![1.0, 2.0])
+        }
+
+        fn to_kelvin(&self, temperature: Celsius) -> f64 {
+            temperature.0 + 273.15
+        }
+
+        fn describe(&self, values: Vec<f64>) -> (usize, f64, String) {
+            let total = values.iter().sum();
+            (values.len(), total, "summed".to_string())
+        }
+    }
+
+    // A parameter type with its own extraction, through WrenTryFrom
+    pub struct Celsius(f64);
+
+    impl ruwren::foreign_v2::WrenTryFrom for Celsius {
+        const SLOT_TYPE: Option<SlotType> = Some(SlotType::Num);
+
+        fn try_from_vm(vm: &VM, slot: SlotId, _scratch_start: SlotId) -> Option<Self> {
+            vm.get_slot_double(slot)
+                .filter(|degrees| *degrees >= -273.15)
+                .map(Celsius)
+        }
+    }
+
+    pub fn non_negative(count: &f64) -> Result<(), String> {
+        if *count < 0.0 {
+            Err(format!("{} is negative", count))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Rebuilds a Counter from its halves, the way a Wren call does
+    pub fn counter_from_parts(created: u32, count: f64) -> (u32, f64) {
+        let mut class = CounterClass { created };
+        let mut instance = CounterInstance { count };
+        let counter = Counter::from(&CounterWrapper::new(&mut class, &mut instance));
+        (counter.created, counter.count)
+    }
+
+    #[derive(WrenFrom)]
+    pub struct Meters(f64);
+
+    #[derive(WrenFrom)]
+    pub struct Runner {
+        name: String,
+    }
+
+    #[derive(WrenFrom)]
+    #[wren(from = f64)]
+    pub struct Minutes {
+        seconds: f64,
+    }
+
+    impl From<f64> for Minutes {
+        fn from(minutes: f64) -> Self {
+            Minutes {
+                seconds: minutes * 60.0,
+            }
+        }
+    }
+
+    // Display, but not WrenTo
+    #[derive(Debug)]
+    pub struct DivideByZero;
+
+    impl std::fmt::Display for DivideByZero {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "can't divide by zero")
+        }
+    }
+
+    #[derive(WrenObject, Clone, Debug)]
+    pub struct Counter {
+        #[wren(accessor, validator = "non_negative")]
+        count: f64,
+        #[wren(static_member, accessor)]
+        created: u32,
+        // Rust-side only, Wren never sees it
+        #[wren(skip)]
+        history: Vec<f64>,
+    }
+
+    #[wren_impl(extended)]
+    impl Counter {
+        #[wren_impl(allocator)]
+        fn alloc() -> CounterClass {
+            CounterClass { created: 0 }
+        }
+
+        #[wren_impl(constructor)]
+        fn construct(&mut self, start: f64) -> Result<CounterInstance, String> {
+            if start < 0.0 {
+                return Err(format!("a Counter can't start at {}", start));
+            }
+            self.created += 1;
+            Ok(CounterInstance { count: start })
+        }
+
+        #[wren_impl(getter)]
+        fn created(&self) -> u32 {
+            self.created
+        }
+
+        // Takes &self, so the class is only borrowed immutably and can be read again
+        #[wren_impl(receive_vm)]
+        fn created_via_vm(&self, vm: &VM) -> u32 {
+            vm.use_class::<CounterInstance, _, _>(|_, class| class.unwrap().created)
+        }
+
+        #[wren_impl(instance, getter)]
+        fn count(&self) -> f64 {
+            self.count
+        }
+
+        #[wren_impl(instance, setter)]
+        fn count(&mut self, count: f64) {
+            self.count = count;
+        }
+
+        // counter[offset] reads the count shifted by offset
+        #[wren_impl(instance, subscript_getter)]
+        fn shifted(&self, offset: f64) -> f64 {
+            self.count + offset
+        }
+
+        // counter[offset] = value sets the count so that counter[offset] reads value
+        #[wren_impl(instance, subscript_setter)]
+        fn shift_to(&mut self, offset: f64, value: f64) {
+            self.count = value - offset;
+        }
+
+        #[wren_impl(instance)]
+        fn increment(&mut self) {
+            self.count += 1.0;
+        }
+
+        #[wren_impl(instance)]
+        fn doubled(&self) -> f64 {
+            self.scaled(2.0)
+        }
+
+        #[wren_impl(instance)]
+        fn reset(&mut self) -> u32 {
+            self.set_count(0.0);
+            self.set_created(self.get_created() + 100);
+            self.get_created()
+        }
+
+        #[wren_impl(instance, pass_through)]
+        pub fn scaled(&self, factor: f64) -> f64 {
+            self.count * factor
+        }
+    }
+
+    // More Counter methods, registered through the main block's `extended`
+    mod counter_extras {
+        use super::{CounterClass, CounterInstance, CounterWrapper};
+        use ruwren::wren_impl;
+
+        #[wren_impl(extend)]
+        impl Counter {
+            #[wren_impl(getter)]
+            fn kind(&self) -> String {
+                "counter".to_string()
+            }
+
+            #[wren_impl(instance)]
+            fn decrement(&mut self) {
+                self.count -= 1.0;
+            }
+        }
+    }
+
+    #[derive(WrenObject, Default, Clone)]
+    pub struct Money {
+        cents: f64,
+    }
+
+    #[wren_impl]
+    impl Money {
+        #[wren_impl(constructor)]
+        fn construct(&self, cents: f64) -> Option<MoneyInstance> {
+            (cents.fract() == 0.0).then_some(MoneyInstance { cents })
+        }
+
+        #[wren_impl(instance, getter)]
+        fn cents(&self) -> f64 {
+            self.cents
+        }
+
+        #[wren_impl(instance, operator = "+", object(other))]
+        fn plus(&self, other: Money) -> Money {
+            Money {
+                cents: self.cents + other.cents,
+            }
+        }
+
+        #[wren_impl(instance, operator = "-")]
+        fn negate(&self) -> Money {
+            Money { cents: -self.cents }
+        }
+
+        #[wren_impl(instance, operator = "<", object(other))]
+        fn less_than(&self, other: Money) -> bool {
+            self.cents < other.cents
+        }
+
+        #[wren_impl(instance, operator = "==", object(other))]
+        fn equals(&self, other: Option<Money>) -> bool {
+            other.is_some_and(|other| other.cents == self.cents)
+        }
+
+        #[wren_impl(to_string)]
+        fn display(&self) -> String {
+            format!("${:.2}", self.cents / 100.0)
+        }
+    }
+
+    #[derive(WrenObject, Default)]
+    pub struct Inspector;
+
+    #[wren_impl]
+    impl Inspector {
+        #[wren_impl(object(counter))]
+        fn describe(&self, counter: Option<Counter>) -> String {
+            match counter {
+                Some(counter) => format!("counter at {}", counter.count),
+                None => "not a counter".to_string(),
+            }
+        }
+
+        #[wren_impl(object(a, b))]
+        fn total(&self, a: Counter, b: Counter) -> f64 {
+            a.count + b.count
+        }
+
+        // Called from Wren as scale(counter, factor)
+        #[wren_impl(object_slot(name = counter, slot = 1))]
+        fn scale(&self, factor: f64, counter: Counter) -> f64 {
+            counter.count * factor
+        }
+
+        #[wren_impl(object(counter))]
+        fn history(&self, counter: Counter) -> usize {
+            counter.history.len()
+        }
+
+        #[wren_impl(receive_vm)]
+        fn slots(&self, _extra: f64, vm: &VM) -> u32 {
+            vm.get_slot_count() as u32
+        }
+
+        #[wren_impl(on_error = "crate::classes::explain")]
+        fn halve(&self, value: f64) -> f64 {
+            value / 2.0
+        }
+    }
+
+    pub fn explain(vm: &VM, method: &str, slot: SlotId, expected: &str) {
+        vm.set_slot_string(
+            0,
+            format!("{} wants a {} in slot {}", method, expected, slot),
+        );
+        vm.abort_fiber(0);
+    }
+
+    #[derive(WrenObject, Default)]
+    pub struct Ledger;
+
+    #[wren_impl(ignore_unannotated)]
+    impl Ledger {
+        #[wren_impl(object(counter))]
+        fn balance(&self, counter: Counter) -> f64 {
+            counter.count
+        }
+
+        #[wren_impl]
+        fn entries(&self) -> f64 {
+            0.0
+        }
+
+        // Takes a parameter Wren can't pass, so it only compiles because it's ignored
+        fn audit(&self, _log: &mut Vec<String>) {}
+    }
+
+    #[derive(WrenObject, Default)]
+    pub struct Echo;
+
+    #[wren_impl]
+    impl Echo {
+        fn echo(&self, value: f64) -> f64 {
+            value
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct BelowAbsoluteZero(f64);
+
+    impl std::fmt::Display for BelowAbsoluteZero {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{} K is below absolute zero", self.0)
+        }
+    }
+
+    #[derive(WrenObject, Default)]
+    pub struct Temperature {
+        kelvin: f64,
+    }
+
+    #[wren_impl]
+    impl Temperature {
+        #[wren_impl(constructor)]
+        fn construct(&self, kelvin: f64) -> Result<TemperatureInstance, BelowAbsoluteZero> {
+            if kelvin < 0.0 {
+                Err(BelowAbsoluteZero(kelvin))
+            } else {
+                Ok(TemperatureInstance { kelvin })
+            }
+        }
+
+        #[wren_impl(instance, getter)]
+        fn kelvin(&self) -> f64 {
+            self.kelvin
+        }
+    }
+
+    #[derive(WrenObject, Default)]
+    pub struct Fragile {
+        limit: f64,
+    }
+
+    #[wren_impl(version = "2")]
+    impl Fragile {
+        #[wren_impl(constructor)]
+        fn construct(&self, limit: f64) -> Result<FragileInstance, String> {
+            assert!(limit > 0.0, "limit must be positive");
+            Ok(FragileInstance { limit })
+        }
+
+        #[wren_impl(instance)]
+        fn check(&self, value: f64) -> f64 {
+            if value > self.limit {
+                panic!("{} is over the limit of {}", value, self.limit);
+            }
+            value
+        }
+
+        fn twice(&self, value: f64) -> f64 {
+            value * 2.0
+        }
+
+        #[wren_impl(object(counter))]
+        fn count_of(&self, counter: Counter) -> f64 {
+            counter.count
+        }
+    }
+
+    #[derive(WrenObject, Default)]
+    pub struct ConstructionError {
+        message: String,
+    }
+
+    impl From<String> for ConstructionError {
+        fn from(message: String) -> Self {
+            ConstructionError { message }
+        }
+    }
+
+    #[wren_impl]
+    impl ConstructionError {
+        #[wren_impl(instance, getter)]
+        fn message(&self) -> String {
+            self.message.clone()
+        }
+    }
+
+    #[derive(WrenObject, Default)]
+    pub struct Vault;
+
+    #[wren_impl]
+    impl Vault {
+        // Takes no arguments, so the allocator only has slot 0 to build the error in
+        #[wren_impl(constructor, error_type = "ConstructionError")]
+        fn construct(&self) -> Result<VaultInstance, String> {
+            Err("the vault is sealed".to_string())
+        }
+    }
+
+    #[derive(WrenObject, Clone, Default)]
+    pub struct Pair(pub f64, pub f64);
+
+    #[wren_impl]
+    impl Pair {
+        #[wren_impl(constructor)]
+        fn construct(&self, a: f64, b: f64) -> PairInstance {
+            PairInstance(a, b)
+        }
+
+        #[wren_impl(instance)]
+        fn sum(&self) -> f64 {
+            self.0 + self.1
+        }
+    }
+
+    #[derive(WrenObject, Clone, Default)]
+    pub struct Scaled(f64, #[wren(static_member)] f64);
+
+    #[wren_impl]
+    impl Scaled {
+        // Asks Wren for its factor, the first time a Scaled is made
+        #[wren_impl(allocator)]
+        fn alloc(vm: &VM) -> ScaledClass {
+            if vm.has_variable("test/classes", "Tripled") {
+                ScaledClass(3.0)
+            } else {
+                ScaledClass(1.0)
+            }
+        }
+
+        #[wren_impl(constructor)]
+        fn construct(&self, value: f64) -> Result<ScaledInstance, String> {
+            Ok(ScaledInstance(value))
+        }
+
+        #[wren_impl(instance)]
+        fn value(&self) -> f64 {
+            self.instance.0 * self.class.0
+        }
+    }
+
+    #[derive(WrenObject, Default)]
+    pub struct Strict;
+
+    #[wren_impl(validate_inputs)]
+    impl Strict {
+        fn repeat(&self, text: WrenString, times: u32) -> String {
+            text.into_string().unwrap().repeat(times as usize)
+        }
+
+        fn maybe(&self, value: Option<f64>) -> bool {
+            value.is_some()
+        }
+
+        #[wren_impl(on_error = "crate::classes::explain")]
+        fn half(&self, value: f64) -> f64 {
+            value / 2.0
+        }
+
+        #[wren_impl(object(counter))]
+        fn count_of(&self, counter: Counter) -> f64 {
+            counter.count
+        }
+    }
+
+    // No Default, the class is set up by ConfiguredClass::with_greeting instead
+    #[derive(WrenObject)]
+    pub struct Configured {
+        #[wren(static_member)]
+        greeting: String,
+    }
+
+    impl ConfiguredClass {
+        pub fn with_greeting() -> Self {
+            ConfiguredClass {
+                greeting: "Howdy".to_string(),
+            }
+        }
+    }
+
+    #[wren_impl(class_default = "ConfiguredClass::with_greeting")]
+    impl Configured {
+        #[wren_impl(constructor)]
+        fn construct(&self) -> Result<ConfiguredInstance, String> {
+            Ok(ConfiguredInstance {})
+        }
+
+        #[wren_impl(getter)]
+        fn greeting(&self) -> String {
+            self.greeting.clone()
+        }
+    }
+
+    #[derive(WrenObject, Clone, Default)]
+    pub enum Shape {
+        #[default]
+        Empty,
+        Circle(f64),
+        Square,
+    }
+
+    #[wren_impl]
+    impl Shape {
+        #[wren_impl(constructor)]
+        fn construct(&self, radius: f64) -> Result<ShapeInstance, String> {
+            match radius {
+                0.0 => Ok(Shape::Square.into()),
+                radius => Ok(Shape::Circle(radius).into()),
+            }
+        }
+
+        #[wren_impl(instance, getter)]
+        fn area(&self) -> f64 {
+            match ***self {
+                Shape::Circle(radius) => 3.0 * radius * radius,
+                _ => 0.0,
+            }
+        }
+    }
+
+    #[derive(WrenObject, Clone, Copy, Default)]
+    #[repr(u8)]
+    pub enum Level {
+        #[default]
+        Low,
+        Custom(u8),
+        High = 10,
+        Higher,
+    }
+
+    #[wren_impl]
+    impl Level {
+        #[wren_impl(constructor)]
+        fn construct(&self, level: u8) -> LevelInstance {
+            match level {
+                0 => Level::Low,
+                10 => Level::High,
+                11 => Level::Higher,
+                level => Level::Custom(level),
+            }
+            .into()
+        }
+
+        #[wren_impl(instance, getter)]
+        fn custom(&self) -> Option<u8> {
+            match ***self {
+                Level::Custom(level) => Some(level),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(WrenObject, Default)]
+    #[wren(rename = "Vec2")]
+    pub struct RsVec2;
+
+    #[wren_impl]
+    impl RsVec2 {
+        #[wren_impl(rename = "length")]
+        fn rs_length(&self, x: f64, y: f64) -> f64 {
+            (x * x + y * y).sqrt()
+        }
+    }
+
+    #[derive(WrenObject, Default)]
+    #[wren(concrete(name = NumberStack, T = f64))]
+    #[wren(concrete(name = TextStack, T = String))]
+    pub struct Stack<T: Clone> {
+        items: Vec<T>,
+    }
+
+    #[wren_impl]
+    impl NumberStack {
+        #[wren_impl(instance)]
+        fn push(&mut self, value: f64) {
+            self.items.push(value);
+        }
+
+        #[wren_impl(instance)]
+        fn sum(&self) -> f64 {
+            self.items.iter().sum()
+        }
+    }
+
+    #[wren_impl]
+    impl TextStack {
+        #[wren_impl(instance)]
+        fn push(&mut self, value: WrenString) {
+            self.items.push(value.into_string().unwrap());
+        }
+
+        #[wren_impl(instance)]
+        fn join(&self) -> String {
+            self.items.join(" ")
+        }
+    }
+
+    #[derive(WrenObject, Default)]
+    #[wren(concrete(name = Ring4, N = 4))]
+    pub struct Ring<const N: usize> {
+        total: f64,
+    }
+
+    impl<const N: usize> Ring<N> {
+        pub const CAPACITY: usize = N;
+    }
+
+    #[wren_impl]
+    impl Ring4 {
+        #[wren_impl(instance)]
+        fn add(&mut self, value: f64) {
+            self.total += value;
+        }
+
+        #[wren_impl(instance)]
+        fn average(&self) -> f64 {
+            self.total / Ring4::CAPACITY as f64
+        }
+    }
+
+    pub static RELEASED: std::sync::Mutex<Vec<u32>> = std::sync::Mutex::new(vec![]);
+
+    pub fn release(handle: u32) {
+        RELEASED.lock().unwrap().push(handle);
+    }
+
+    pub static RELEASED_LABELS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(vec![]);
+
+    pub fn release_label(label: String) {
+        RELEASED_LABELS.lock().unwrap().push(label);
+    }
+
+    #[derive(WrenObject, Default)]
+    pub struct Resource {
+        #[wren(drop_on_wren_free = "crate::classes::release")]
+        handle: u32,
+        // Not Copy, so it's moved out to be released
+        #[wren(drop_on_wren_free = "crate::classes::release_label")]
+        label: String,
+    }
+
+    #[wren_impl]
+    impl Resource {
+        #[wren_impl(constructor)]
+        fn construct(&self, handle: u32) -> Result<ResourceInstance, String> {
+            Ok(ResourceInstance {
+                handle,
+                label: format!("resource {}", handle),
+            })
+        }
+    }
+
+    pub static FINALIZED: std::sync::Mutex<Vec<u32>> = std::sync::Mutex::new(vec![]);
+
+    #[derive(WrenObject, Default)]
+    pub struct Session {
+        id: u32,
+    }
+
+    #[wren_impl]
+    impl Session {
+        #[wren_impl(constructor)]
+        fn construct(&self, id: u32) -> Result<SessionInstance, String> {
+            Ok(SessionInstance { id })
+        }
+
+        #[wren_impl(finalizer)]
+        fn close(&mut self) {
+            FINALIZED.lock().unwrap().push(self.id);
+        }
+    }
+
+    #[derive(WrenObject, Default)]
+    pub struct Thermostat {
+        target: f64,
+    }
+
+    #[wren_mock]
+    impl Thermostat {
+        #[wren_impl(instance, setter)]
+        fn target(&mut self, target: f64) {
+            self.target = target;
+        }
+
+        #[wren_impl(instance)]
+        fn heating(&self, current: f64) -> bool {
+            current < self.target
+        }
+
+        fn describe(&self, celsius: f64) -> String {
+            format!("{} C", celsius)
+        }
+    }
+}
+
+wren_module! {
+    mod test_classes {
+        pub crate::classes::Calculator;
+        pub crate::classes::Counter;
+        pub crate::classes::Money;
+        pub crate::classes::Inspector;
+        pub crate::classes::Temperature;
+        pub(crate) crate::classes::Fragile;
+        pub crate::classes::ConstructionError;
+        pub crate::classes::Vault;
+        pub crate::classes::Resource;
+        pub crate::classes::Session;
+        pub crate::classes::Pair;
+        pub crate::classes::Scaled;
+        pub crate::classes::Strict;
+        pub crate::classes::Configured;
+        pub crate::classes::Shape;
+        pub crate::classes::Level;
+        pub crate::classes::RsVec2;
+        pub crate::classes::NumberStack;
+        pub crate::classes::TextStack;
+        pub crate::classes::Ring4;
+    }
+}
+
+wren_module! {
+    module = "test/ledger_book",
+    mod test_ledger {
+        pub crate::classes::Ledger;
+        #[wren_module(secondary)]
+        pub crate::classes::Money;
+    }
+}
+
+wren_module_v2! {
+    mod test_builder {
+        crate::classes::Echo;
+    }
+}
+
+#[cfg(feature = "stubs")]
+mod stub_classes {
+    use super::*;
+
+    #[derive(WrenObject, Default)]
+    pub struct Greeter;
+
+    #[wren_impl]
+    impl Greeter {
+        #[wren_impl(constructor)]
+        fn construct(&self, _greeting: WrenString) -> Result<GreeterInstance, String> {
+            Ok(GreeterInstance)
+        }
+
+        #[wren_impl(instance)]
+        fn greet(&self, name: WrenString) -> String {
+            format!("Hello, {}!", name.into_string().unwrap())
+        }
+    }
+
+    wren_module! {
+        stub_path = "stubs",
+        pub mod test_stubs {
+            pub crate::stub_classes::Greeter;
+        }
+    }
+}
+
+const CLASSES_SRC: &str = r#"
+class Calculator {
+    foreign static add(a, b)
+    foreign static greet(name)
+    foreign static sign(value)
+    foreign static nothing()
+    foreign static sum(values)
+    foreign static pace(runner, distance, time)
+    foreign static join(words, sep)
+    foreign static divide(a, b)
+    foreign static safe_sqrt(a)
+    foreign static boxed(a)
+    foreign static shared()
+    foreign static pair()
+    foreign static to_kelvin(temperature)
+    foreign static describe(values)
+}
+
+foreign class Counter {
+    construct new(start) {}
+
+    foreign static created
+    foreign static created_via_vm()
+    foreign count
+    foreign count=(value)
+    foreign [offset]
+    foreign [offset]=(value)
+    foreign increment()
+    foreign doubled()
+    foreign reset()
+
+    foreign static kind
+    foreign decrement()
+}
+
+foreign class Temperature {
+    construct new(kelvin) {}
+
+    foreign kelvin
+}
+
+foreign class Fragile {
+    construct new(limit) {}
+
+    foreign check(value)
+    foreign static twice(value)
+    foreign static count_of(counter)
+}
+
+foreign class ConstructionError {
+    foreign message
+}
+
+foreign class Vault {
+    construct new() {}
+}
+
+foreign class Resource {
+    construct new(handle) {}
+}
+
+foreign class Session {
+    construct new(id) {}
+}
+
+foreign class Pair {
+    construct new(a, b) {}
+
+    foreign sum()
+}
+
+foreign class Scaled {
+    construct new(value) {}
+
+    foreign value()
+}
+
+var Tripled = true
+
+class Strict {
+    foreign static repeat(text, times)
+    foreign static maybe(value)
+    foreign static half(value)
+    foreign static count_of(counter)
+}
+
+foreign class Configured {
+    construct new() {}
+
+    foreign static greeting
+}
+
+foreign class Shape {
+    construct new(radius) {}
+
+    foreign static Empty
+    foreign static Square
+    foreign area
+}
+
+foreign class Level {
+    construct new(level) {}
+
+    foreign custom
+    foreign static Low
+    foreign static High
+    foreign static Higher
+}
+
+class Vec2 {
+    foreign static length(x, y)
+}
+
+foreign class NumberStack {
+    construct new() {}
+
+    foreign push(value)
+    foreign sum()
+}
+
+foreign class TextStack {
+    construct new() {}
+
+    foreign push(value)
+    foreign join()
+}
+
+foreign class Ring4 {
+    construct new() {}
+
+    foreign add(value)
+    foreign average()
+}
+
+foreign class Money {
+    construct new(cents) {}
+
+    foreign cents
+    foreign +(other)
+    foreign -
+    foreign <(other)
+    foreign ==(other)
+    foreign toString
+}
+
+class Inspector {
+    foreign static describe(counter)
+    foreign static total(a, b)
+    foreign static scale(counter, factor)
+    foreign static history(counter)
+    foreign static slots(extra)
+    foreign static halve(value)
+}
+"#;
+
+fn vm_with_classes() -> VMWrapper {
+    let mut lib = ModuleLibrary::new();
+    test_classes::publish_module(&mut lib);
+    let vm = VMConfig::new().library(&lib).build();
+    vm.interpret("test/classes", CLASSES_SRC).unwrap();
+    vm
+}
+
+fn get_number(vm: &VMWrapper, name: &str) -> Option<f64> {
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", name, 0);
+        vm.get_slot_double(0)
+    })
+}
+
+fn get_string(vm: &VMWrapper, name: &str) -> Option<String> {
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", name, 0);
+        vm.get_slot_string(0)
+    })
+}
+
+fn get_bool(vm: &VMWrapper, name: &str) -> Option<bool> {
+    vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", name, 0);
+        vm.get_slot_bool(0)
+    })
+}
+
+#[cfg(feature = "stubs")]
+#[test]
+fn wren_stubs() {
+    let stubs = test_classes::wren_stubs();
+    assert!(stubs.contains("foreign class Counter {\n    construct new(start) {}\n"));
+    assert!(stubs.contains("    foreign static created\n"));
+    assert!(stubs.contains("    foreign count=(count)\n"));
+    assert!(stubs.contains("    foreign [offset]=(value)\n"));
+    assert!(stubs.contains("    foreign decrement()\n"));
+    assert!(stubs.contains("    foreign static scale(counter, factor)\n"));
+    assert!(stubs.contains("foreign class Vec2 {"));
+    assert!(stubs.contains("    foreign static length(x, y)\n"));
+    assert!(stubs.contains("    foreign static Square\n"));
+
+    let out_dir = std::env::temp_dir().join("ruwren-stubs-test");
+    std::env::set_var("OUT_DIR", &out_dir);
+    let path = stub_classes::test_stubs::write_stubs().unwrap();
+    assert_eq!(path, out_dir.join("stubs/test/stubs.wren"));
+    assert_eq!(
+        std::fs::read_to_string(path).unwrap(),
+        "foreign class Greeter {\n    construct new(_greeting) {}\n\n    foreign greet(name)\n}\n"
+    );
+}
+
+#[test]
+fn basic_class() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Calculator
+    var Sum = Calculator.add(2, 40)
+    var Greeting = Calculator.greet("Wren")
+    var Sign = Calculator.sign(-3)
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Sum"), Some(42.0));
+    assert_eq!(get_string(&vm, "Greeting").as_deref(), Some("Hello, Wren!"));
+    assert_eq!(get_string(&vm, "Sign").as_deref(), Some("negative"));
+}
+
+#[test]
+fn newtype_parameters() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Calculator
+    var Pace = Calculator.pace("Ada", 400, 1.5)
+    var Error = Fiber.new { Calculator.pace(7, 400, 1.5) }.try()
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        get_string(&vm, "Pace").as_deref(),
+        Some("Ada ran 400m in 90s")
+    );
+    assert!(get_string(&vm, "Error").is_some());
+}
+
+#[test]
+fn list_parameters() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Calculator
+    var Sum = Calculator.sum([1, 2, 3.5])
+    var Empty = Calculator.sum([])
+    var Joined = Calculator.join(["a", "b", "c"], "-")
+    var Error = Fiber.new { Calculator.sum([1, "two"]) }.try()
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Sum"), Some(6.5));
+    assert_eq!(get_number(&vm, "Empty"), Some(0.0));
+    assert_eq!(get_string(&vm, "Joined").as_deref(), Some("a-b-c"));
+    assert!(get_string(&vm, "Error").is_some());
+}
+
+#[test]
+fn result_returns() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Calculator
+    var Quotient = Calculator.divide(6, 4)
+    var Error = Fiber.new { Calculator.divide(1, 0) }.try()
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Quotient"), Some(1.5));
+    assert_eq!(
+        get_string(&vm, "Error").as_deref(),
+        Some("can't divide by zero")
+    );
+}
+
+#[test]
+fn custom_parameter_types() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Calculator
+    var Kelvin = Calculator.to_kelvin(20)
+    var Error = Fiber.new { Calculator.to_kelvin(-300) }.try()
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Kelvin"), Some(293.15));
+    assert!(get_string(&vm, "Error")
+        .unwrap()
+        .starts_with("failed to get value of type"));
+}
+
+#[test]
+fn tuple_returns() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Calculator
+    var Description = Calculator.describe([1, 2, 3.5])
+    var Count = Description.count
+    var Length = Description[0]
+    var Total = Description[1]
+    var Label = Description[2]
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Count"), Some(3.0));
+    assert_eq!(get_number(&vm, "Length"), Some(3.0));
+    assert_eq!(get_number(&vm, "Total"), Some(6.5));
+    assert_eq!(get_string(&vm, "Label").as_deref(), Some("summed"));
+}
+
+#[test]
+fn option_returns() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Calculator
+    var Root = Calculator.safe_sqrt(9)
+    var NoRoot = Calculator.safe_sqrt(-1)
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Root"), Some(3.0));
+    vm.execute(|vm| {
+        vm.get_variable("main", "NoRoot", 0);
+        assert_eq!(vm.get_slot_type(0), SlotType::Null);
+    });
+}
+
+#[test]
+fn smart_pointer_returns() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Calculator
+    var Boxed = Calculator.boxed(3)
+    var Shared = Calculator.shared()
+    var Pair = Calculator.pair()[0] + Calculator.pair()[1]
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Boxed"), Some(3.0));
+    assert_eq!(get_string(&vm, "Shared").as_deref(), Some("shared"));
+    assert_eq!(get_number(&vm, "Pair"), Some(3.0));
+}
+
+#[test]
+fn unit_returns_null() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Calculator
+    var Nothing = Calculator.nothing()
+    "#,
+    )
+    .unwrap();
+
+    vm.execute(|vm| {
+        vm.get_variable("main", "Nothing", 0);
+        assert_eq!(vm.get_slot_type(0), SlotType::Null);
+    });
+}
+
+#[test]
+fn getters_and_setters() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Counter
+    var counter = Counter.new(5)
+    counter.increment()
+    var Incremented = counter.count
+    counter.count = 10
+    var Set = counter.count
+    var Shifted = counter[2]
+    counter[3] = 20
+    var ShiftedTo = counter.count
+    Counter.new(0)
+    var Created = Counter.created
+    var CreatedViaVm = Counter.created_via_vm()
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Incremented"), Some(6.0));
+    assert_eq!(get_number(&vm, "Set"), Some(10.0));
+    assert_eq!(get_number(&vm, "Shifted"), Some(12.0));
+    assert_eq!(get_number(&vm, "ShiftedTo"), Some(17.0));
+    assert_eq!(get_number(&vm, "Created"), Some(2.0));
+    assert_eq!(get_number(&vm, "CreatedViaVm"), Some(2.0));
+}
+
+#[test]
+fn field_accessors() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Counter
+    var counter = Counter.new(5)
+    var Created = counter.reset()
+    var Count = counter.count
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Created"), Some(101.0));
+    assert_eq!(get_number(&vm, "Count"), Some(0.0));
+}
+
+#[test]
+fn drop_on_wren_free() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Resource
+    var Kept = Resource.new(7)
+    Resource.new(8)
+    "#,
+    )
+    .unwrap();
+    vm.collect_garbage();
+    assert_eq!(*classes::RELEASED.lock().unwrap(), vec![8]);
+    assert_eq!(
+        *classes::RELEASED_LABELS.lock().unwrap(),
+        vec!["resource 8"]
+    );
+
+    drop(vm);
+    assert_eq!(*classes::RELEASED.lock().unwrap(), vec![8, 7]);
+    assert_eq!(
+        *classes::RELEASED_LABELS.lock().unwrap(),
+        vec!["resource 8", "resource 7"]
+    );
+}
+
+#[test]
+fn finalizers() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Session
+    var Kept = Session.new(1)
+    Session.new(2)
+    "#,
+    )
+    .unwrap();
+    vm.collect_garbage();
+    assert_eq!(*classes::FINALIZED.lock().unwrap(), vec![2]);
+
+    drop(vm);
+    assert_eq!(*classes::FINALIZED.lock().unwrap(), vec![2, 1]);
+}
+
+#[test]
+fn tuple_structs() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Pair, Scaled
+    var Sum = Pair.new(1, 2).sum()
+    var Value = Scaled.new(5).value()
+    var Halves = Pair.new(4, 6)
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Sum"), Some(3.0));
+    assert_eq!(get_number(&vm, "Value"), Some(15.0));
+    // Public tuple fields stay public on the instance
+    vm.execute(|vm| {
+        vm.get_variable("main", "Halves", 0);
+        let halves = vm.get_slot_foreign::<classes::PairInstance>(0).unwrap();
+        assert_eq!((halves.0, halves.1), (4.0, 6.0));
+    });
+}
+
+#[test]
+fn validate_inputs() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Strict, Counter
+    var Repeated = Strict.repeat("ab", 2)
+    var Maybe = Strict.maybe(null)
+    var Error = Fiber.new { Strict.repeat("ab", "2") }.try()
+    var Handled = Fiber.new { Strict.half("4") }.try()
+    var Count = Strict.count_of(Counter.new(5))
+    var NotObject = Fiber.new { Strict.count_of(5) }.try()
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_string(&vm, "Repeated").as_deref(), Some("abab"));
+    assert_eq!(
+        get_string(&vm, "Error").as_deref(),
+        Some("expected Num for slot 2 but got String")
+    );
+    // Failed checks go through on_error, like failed extractions
+    assert_eq!(
+        get_string(&vm, "Handled").as_deref(),
+        Some("half wants a f64 in slot 1")
+    );
+    assert_eq!(
+        get_string(&vm, "NotObject").as_deref(),
+        Some("expected Foreign for slot 1 but got Num")
+    );
+    vm.execute(|vm| {
+        vm.get_variable("main", "Count", 0);
+        assert_eq!(vm.get_slot_double(0), Some(5.0));
+    });
+    vm.execute(|vm| {
+        vm.get_variable("main", "Maybe", 0);
+        assert_eq!(vm.get_slot_bool(0), Some(false));
+    });
+}
+
+#[test]
+fn class_default() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Configured
+    var Greeting = Configured.greeting
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_string(&vm, "Greeting").as_deref(), Some("Howdy"));
+}
+
+#[test]
+fn enum_objects() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Shape
+    var Area = Shape.new(2).area
+    var Empty = Shape.Empty
+    var Square = Shape.Square
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Area"), Some(12.0));
+    assert_eq!(get_number(&vm, "Empty"), Some(0.0));
+    assert_eq!(get_number(&vm, "Square"), Some(1.0));
+}
+
+#[test]
+fn enum_discriminants() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Level
+    var Low = Level.Low
+    var High = Level.High
+    var Higher = Level.Higher
+    var Custom = Level.new(4).custom
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Low"), Some(0.0));
+    assert_eq!(get_number(&vm, "High"), Some(10.0));
+    assert_eq!(get_number(&vm, "Higher"), Some(11.0));
+    assert_eq!(get_number(&vm, "Custom"), Some(4.0));
+}
+
+#[test]
+fn renamed_class_and_method() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Vec2
+    var Length = Vec2.length(3, 4)
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Length"), Some(5.0));
+}
+
+#[test]
+fn generic_objects() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for NumberStack, TextStack
+    var numbers = NumberStack.new()
+    numbers.push(1.5)
+    numbers.push(2)
+    var Sum = numbers.sum()
+    var words = TextStack.new()
+    words.push("generic")
+    words.push("stacks")
+    var Joined = words.join()
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Sum"), Some(3.5));
+    assert_eq!(
+        get_string(&vm, "Joined"),
+        Some("generic stacks".to_string())
+    );
+}
+
+#[test]
+fn const_generic_objects() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Ring4
+    var ring = Ring4.new()
+    ring.add(3)
+    ring.add(5)
+    var Average = ring.average()
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Average"), Some(2.0));
+}
+
+#[test]
+fn object_parameters() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Counter, Inspector
+    var a = Counter.new(3)
+    var b = Counter.new(4)
+    var Description = Inspector.describe(a)
+    var NotCounter = Inspector.describe("a string")
+    var Total = Inspector.total(a, b)
+    var Scaled = Inspector.scale(a, 2)
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        get_string(&vm, "Description").as_deref(),
+        Some("counter at 3")
+    );
+    assert_eq!(
+        get_string(&vm, "NotCounter").as_deref(),
+        Some("not a counter")
+    );
+    assert_eq!(get_number(&vm, "Total"), Some(7.0));
+    assert_eq!(get_number(&vm, "Scaled"), Some(6.0));
+}
+
+#[test]
+fn extend_blocks() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Counter
+    var counter = Counter.new(3)
+    counter.decrement()
+    counter.increment()
+    counter.decrement()
+    var Count = counter.count
+    var Kind = Counter.kind
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Count"), Some(2.0));
+    assert_eq!(get_string(&vm, "Kind").as_deref(), Some("counter"));
+}
+
+#[test]
+fn operators() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Money
+    var a = Money.new(150)
+    var b = Money.new(25)
+    var Sum = (a + b).cents
+    var Negated = (-a).cents
+    var Less = b < a
+    var Same = a == Money.new(150)
+    var NotMoney = a == 150
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Sum"), Some(175.0));
+    assert_eq!(get_number(&vm, "Negated"), Some(-150.0));
+    assert_eq!(get_bool(&vm, "Less"), Some(true));
+    assert_eq!(get_bool(&vm, "Same"), Some(true));
+    assert_eq!(get_bool(&vm, "NotMoney"), Some(false));
+}
+
+#[test]
+fn optional_constructors() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Money
+    var Cents = Money.new(3).cents
+    var Error = Fiber.new { Money.new(0.5) }.try()
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Cents"), Some(3.0));
+    assert_eq!(
+        get_string(&vm, "Error").as_deref(),
+        Some("constructor returned None")
+    );
+}
+
+#[test]
+fn to_string_methods() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Money
+    var Price = Money.new(150).toString
+    var Label = "costs %(Money.new(25))"
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_string(&vm, "Price").as_deref(), Some("$1.50"));
+    assert_eq!(get_string(&vm, "Label").as_deref(), Some("costs $0.25"));
+}
+
+#[test]
+fn skipped_fields() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Counter, Inspector
+    var History = Inspector.history(Counter.new(3))
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "History"), Some(0.0));
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn field_validators() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Counter, Fragile
+    var counter = Counter.new(1)
+    var Valid = Fragile.count_of(counter)
+    counter.count = -2
+    var fiber = Fiber.new { Fragile.count_of(counter) }
+    var Error = fiber.try()
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Valid"), Some(1.0));
+    assert!(get_string(&vm, "Error")
+        .unwrap()
+        .contains("invalid value for Counter.count: -2 is negative"));
+}
+
+#[test]
+fn custom_constructor() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Counter
+    var fiber = Fiber.new { Counter.new(-1) }
+    var Error = fiber.try()
+    var Start = Counter.new(12).count
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        get_string(&vm, "Error").as_deref(),
+        Some("a Counter can't start at -1")
+    );
+    assert_eq!(get_number(&vm, "Start"), Some(12.0));
+}
+
+#[test]
+fn display_constructor_error() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Temperature
+    var fiber = Fiber.new { Temperature.new(-5) }
+    var Error = fiber.try()
+    var Kelvin = Temperature.new(273.15).kelvin
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        get_string(&vm, "Error").as_deref(),
+        Some("-5 K is below absolute zero")
+    );
+    assert_eq!(get_number(&vm, "Kelvin"), Some(273.15));
+}
+
+#[test]
+fn constructor_error_type() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for ConstructionError, Vault
+    var Error = Fiber.new { Vault.new() }.try()
+    var IsErrorClass = Error is ConstructionError
+    var Message = Error.message
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_bool(&vm, "IsErrorClass"), Some(true));
+    assert_eq!(
+        get_string(&vm, "Message").as_deref(),
+        Some("the vault is sealed")
+    );
+
+    // Without a Wren declaration of the error's class, the message is all that's left
+    let mut lib = ModuleLibrary::new();
+    test_classes::publish_module(&mut lib);
+    let vm = VMConfig::new().library(&lib).build();
+    vm.interpret(
+        "test/classes",
+        "foreign class Vault {\n construct new() {}\n}",
+    )
+    .unwrap();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Vault
+    var Error = Fiber.new { Vault.new() }.try()
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        get_string(&vm, "Error").as_deref(),
+        Some("the vault is sealed (and the error object couldn't be created: no Wren class)")
+    );
+}
+
+#[test]
+fn error_propagation() {
+    let vm = vm_with_classes();
+    let res = vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Calculator
+    Calculator.add("not a number", 1)
+    "#,
+    );
+
+    match res {
+        Err(VMError::Runtime { error, .. }) => {
+            assert!(error.contains("slot 1"), "unexpected error: {}", error)
+        }
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+}
+
+#[test]
+fn receive_vm() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Inspector
+    var Slots = Inspector.slots(0)
+    "#,
+    )
+    .unwrap();
+
+    // The receiver and the single argument
+    assert_eq!(get_number(&vm, "Slots"), Some(2.0));
+}
+
+#[test]
+fn on_error_handler() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Inspector
+    var fiber = Fiber.new { Inspector.halve(false) }
+    var Error = fiber.try()
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        get_string(&vm, "Error").as_deref(),
+        Some("halve wants a f64 in slot 1")
+    );
+}
+
+#[test]
+fn pass_through_helpers() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Counter
+    var Doubled = Counter.new(21).doubled()
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Doubled"), Some(42.0));
+}
+
+#[test]
+fn version_2_catches_panics() {
+    let vm = vm_with_classes();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Fragile
+    var fragile = Fragile.new(10)
+    var Checked = fragile.check(4)
+    var Twice = Fragile.twice(Checked)
+    var CheckError = Fiber.new { fragile.check(11) }.try()
+    var ConstructError = Fiber.new { Fragile.new(0) }.try()
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Checked"), Some(4.0));
+    assert_eq!(get_number(&vm, "Twice"), Some(8.0));
+    assert_eq!(
+        get_string(&vm, "CheckError").as_deref(),
+        Some("11 is over the limit of 10")
+    );
+    assert_eq!(
+        get_string(&vm, "ConstructError").as_deref(),
+        Some("limit must be positive")
+    );
+}
+
+#[test]
+fn cross_module_objects() {
+    let mut lib = ModuleLibrary::new();
+    test_classes::publish_module(&mut lib);
+    test_ledger::publish_module(&mut lib);
+    let vm = VMConfig::new().library(&lib).build();
+    vm.interpret("test/classes", CLASSES_SRC).unwrap();
+    vm.interpret(
+        "test/ledger_book",
+        "class Ledger {\n foreign static balance(counter)\n foreign static entries()\n }",
+    )
+    .unwrap();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Counter
+    import "test/ledger_book" for Ledger
+    var Balance = Ledger.balance(Counter.new(12))
+    var Entries = Ledger.entries()
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Balance"), Some(12.0));
+    assert_eq!(get_number(&vm, "Entries"), Some(0.0));
+}
+
+#[test]
+fn secondary_registrations() {
+    let mut lib = ModuleLibrary::new();
+    test_classes::publish_module(&mut lib);
+    test_ledger::publish_module(&mut lib);
+    let vm = VMConfig::new().library(&lib).build();
+    vm.interpret("test/classes", CLASSES_SRC).unwrap();
+    vm.interpret(
+        "test/ledger_book",
+        "foreign class Money {\n construct new(cents) {}\n foreign cents\n foreign -\n }",
+    )
+    .unwrap();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/classes" for Money
+    import "test/ledger_book" for Money as LedgerMoney
+    var Owed = LedgerMoney.new(250)
+    var Negated = -Owed
+    var Cents = Negated.cents
+    var OwedIsLedgerMoney = Owed is LedgerMoney
+    var NegatedIsMoney = Negated is Money
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Cents"), Some(-250.0));
+    assert_eq!(get_bool(&vm, "OwedIsLedgerMoney"), Some(true));
+    // Values sent from Rust are instances of the primary registration's class
+    assert_eq!(get_bool(&vm, "NegatedIsMoney"), Some(true));
+}
+
+#[test]
+fn module_builder() {
+    let mut builder = test_builder::module_builder();
+    assert_eq!(builder.validate(), Ok(()));
+    builder.merge(test_builder::module_builder());
+    assert_eq!(
+        builder.validate(),
+        Err(ModuleBuildError::DuplicateClass("Echo".to_string()))
+    );
+
+    let mut lib = ModuleLibrary::new();
+    test_builder::publish_module(&mut lib);
+    let vm = VMConfig::new().library(&lib).build();
+    vm.interpret(
+        "test/builder",
+        "class Echo {\n foreign static echo(value)\n }",
+    )
+    .unwrap();
+    vm.interpret(
+        "main",
+        r#"
+    import "test/builder" for Echo
+    var Echoed = Echo.echo(7)
+    "#,
+    )
+    .unwrap();
+
+    assert_eq!(get_number(&vm, "Echoed"), Some(7.0));
+}
+
+#[test]
+fn register_fns() {
+    // Classes can be picked out of a wren_module into a module of your own
+    let mut module = ruwren::Module::new();
+    test_classes::register_calculator(&mut module);
+    test_classes::register_construction_error(&mut module);
+    // Listed as pub(crate), so only usable from this crate
+    test_classes::register_fragile(&mut module);
+    assert!(module.class_exists("Calculator"));
+    assert!(module.class_exists("ConstructionError"));
+    assert!(module.class_exists("Fragile"));
+    assert!(!module.class_exists("Counter"));
+}
+
+#[test]
+fn wrapper_from_parts() {
+    assert_eq!(classes::counter_from_parts(3, 7.5), (3, 7.5));
+}
+
+#[test]
+fn mock_without_vm() {
+    use classes::{Thermostat, ThermostatMock};
+
+    let mut mock = ThermostatMock::new(Thermostat::default().into(), Thermostat::default().into());
+    mock.setter_target(20.0);
+    assert!(mock.heating(18.5));
+    assert!(!mock.heating(21.0));
+
+    mock.mock_describe(|_| "mocked".to_string());
+    assert_eq!(mock.describe(3.0), "mocked");
+    assert_eq!(
+        mock.calls(),
+        &["setter_target", "heating", "heating", "describe"]
+    );
+}
+
+wren_test! {
+    name = wren_test_runs_script,
+    modules = [test_classes],
+    sources = ["test/classes" => CLASSES_SRC],
+    source = r#"
+    import "test/classes" for Calculator
+    if (Calculator.add(2, 3) != 5) Fiber.abort("wrong sum")
+    "#,
+}
+
+wren_test! {
+    name = wren_test_expects_error,
+    modules = [test_classes],
+    sources = ["test/classes" => CLASSES_SRC],
+    source = r#"
+    import "test/classes" for Calculator
+    Calculator.add(2, 3).frobnicate()
+    "#,
+    expect_error = "does not implement 'frobnicate()'",
+}
@@ -0,0 +1,57 @@
+//! The panic hook is process-wide, so this lives in its own test binary
+//! where no other foreign calls can swap it out from under us.
+
+use ruwren::{wren_impl, wren_module, ModuleLibrary, VMConfig, WrenObject};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static HOOK_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(WrenObject, Default)]
+pub struct Probe;
+
+#[wren_impl(no_panic_hook)]
+impl Probe {
+    fn panic_reported(&self) -> bool {
+        let before = HOOK_CALLS.load(Ordering::SeqCst);
+        let _ = std::panic::catch_unwind(|| panic!("probe"));
+        HOOK_CALLS.load(Ordering::SeqCst) > before
+    }
+}
+
+wren_module! {
+    mod probe {
+        pub crate::Probe;
+    }
+}
+
+#[test]
+fn no_panic_hook_keeps_user_hook() {
+    std::panic::set_hook(Box::new(|_| {
+        HOOK_CALLS.fetch_add(1, Ordering::SeqCst);
+    }));
+
+    let mut lib = ModuleLibrary::new();
+    probe::publish_module(&mut lib);
+    let vm = VMConfig::new().library(&lib).build();
+    vm.interpret(
+        "probe",
+        "class Probe {\n foreign static panic_reported()\n }",
+    )
+    .unwrap();
+    vm.interpret(
+        "main",
+        r#"
+    import "probe" for Probe
+    var Reported = Probe.panic_reported()
+    "#,
+    )
+    .unwrap();
+
+    let reported = vm.execute(|vm| {
+        vm.ensure_slots(1);
+        vm.get_variable("main", "Reported", 0);
+        vm.get_slot_bool(0)
+    });
+    drop(std::panic::take_hook());
+    assert_eq!(reported, Some(true));
+}
@@ -0,0 +1,14 @@
+use ruwren::wren_impl;
+
+pub struct Borrowed<'a> {
+    text: &'a str,
+}
+
+#[wren_impl]
+impl<'a> Borrowed<'a> {
+    fn len(&self) -> f64 {
+        self.text.len() as f64
+    }
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use ruwren::WrenObject;
+
+#[derive(WrenObject, Default)]
+pub struct Borrowed<'a> {
+    text: &'a str,
+}
+
+fn main() {}
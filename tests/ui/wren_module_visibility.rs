@@ -0,0 +1,19 @@
+use ruwren::{wren_impl, wren_module, WrenObject};
+
+#[derive(WrenObject, Default)]
+pub struct Hidden;
+
+#[wren_impl]
+impl Hidden {}
+
+wren_module! {
+    mod hidden_classes {
+        crate::Hidden;
+    }
+}
+
+fn main() {
+    // Listed without pub, so only the module itself can register it
+    let mut module = ruwren::Module::new();
+    hidden_classes::register_hidden(&mut module);
+}